@@ -0,0 +1,117 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use zeroize::Zeroize;
+
+use super::Result;
+
+/// A password which is redacted in `Debug` and wiped from memory on drop.
+#[derive(Clone, Default)]
+pub struct Password(String);
+
+impl Password {
+    pub fn new(password: String) -> Self {
+        Self(password)
+    }
+
+    /// Returns the plain text password.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Password {
+    fn from(password: String) -> Self {
+        Self(password)
+    }
+}
+
+impl From<&str> for Password {
+    fn from(password: &str) -> Self {
+        Self(password.to_string())
+    }
+}
+
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Password,
+}
+
+/// Supplies the credentials every time a session is opened, including the
+/// re-opens after a failover, so they can be rotated without recreating the
+/// session.
+pub trait CredentialProvider: Send + Sync {
+    fn credentials(&self) -> Result<Credentials>;
+}
+
+/// Reads the password from a file, e.g. a mounted secret, trailing line
+/// breaks are ignored.
+#[derive(Clone, Debug)]
+pub struct FileCredentialProvider {
+    username: String,
+    path: PathBuf,
+}
+
+impl FileCredentialProvider {
+    pub fn new<U, P>(username: U, path: P) -> Self
+    where
+        U: Into<String>,
+        P: Into<PathBuf>,
+    {
+        Self {
+            username: username.into(),
+            path: path.into(),
+        }
+    }
+}
+
+impl CredentialProvider for FileCredentialProvider {
+    fn credentials(&self) -> Result<Credentials> {
+        let mut password = fs::read_to_string(&self.path).map_err(|err| {
+            format!(
+                "failed to read the password from {}, {}",
+                self.path.display(),
+                err
+            )
+        })?;
+        let len = password.trim_end_matches(['\r', '\n']).len();
+        password.truncate(len);
+        Ok(Credentials {
+            username: self.username.clone(),
+            password: Password::from(password),
+        })
+    }
+}
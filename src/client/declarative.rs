@@ -0,0 +1,842 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+//! Schema as code: a TOML or YAML file declares the storage groups,
+//! templates and devices, it's diffed against the server into a `Plan`
+//! which creates what's missing.
+//!
+//! ```toml
+//! [[storage_groups]]
+//! path = "root.sg1"
+//! ttl = 86400000
+//!
+//! [[templates]]
+//! name = "sensor"
+//! set_on = ["root.sg1.d2"]
+//! measurements = [{ name = "temperature", data_type = "FLOAT" }]
+//!
+//! [[devices]]
+//! path = "root.sg1.d1"
+//! aligned = true
+//! timeseries = [
+//!     { name = "status", data_type = "BOOLEAN", tags = { unit = "none" } },
+//!     { name = "speed", data_type = "DOUBLE", encoding = "GORILLA", alias = "v" },
+//! ]
+//! ```
+//!
+//! Plans only add and update, nothing is deleted, and a declaration the
+//! server can't be changed to, e.g. another data type, is a conflict.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer};
+
+use crate::protocal::{TSCompressionType, TSDataType, TSEncoding};
+
+use super::remote::RpcSession;
+use super::schema::default_encoding;
+use super::{Dictionary, MeasurementSchema, Result, Session, Template, Value};
+
+/// Compressor of the measurements which don't declare one.
+const DEFAULT_COMPRESSOR: TSCompressionType = TSCompressionType::SNAPPY;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchemaFile {
+    #[serde(default)]
+    pub storage_groups: Vec<StorageGroupSpec>,
+    #[serde(default)]
+    pub templates: Vec<TemplateSpec>,
+    #[serde(default)]
+    pub devices: Vec<DeviceSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageGroupSpec {
+    pub path: String,
+    /// In the timestamp precision of the server, ms by default.
+    pub ttl: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateSpec {
+    pub name: String,
+    #[serde(default)]
+    pub aligned: bool,
+    pub measurements: Vec<MeasurementSpec>,
+    /// The paths the template is set on.
+    #[serde(default)]
+    pub set_on: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeasurementSpec {
+    pub name: String,
+    #[serde(deserialize_with = "parse")]
+    pub data_type: TSDataType,
+    /// `default_encoding` of the data type if it's not set.
+    #[serde(default, deserialize_with = "parse_option")]
+    pub encoding: Option<TSEncoding>,
+    /// SNAPPY if it's not set.
+    #[serde(default, deserialize_with = "parse_option")]
+    pub compressor: Option<TSCompressionType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceSpec {
+    pub path: String,
+    #[serde(default)]
+    pub aligned: bool,
+    pub timeseries: Vec<TimeseriesSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeseriesSpec {
+    pub name: String,
+    #[serde(deserialize_with = "parse")]
+    pub data_type: TSDataType,
+    #[serde(default, deserialize_with = "parse_option")]
+    pub encoding: Option<TSEncoding>,
+    #[serde(default, deserialize_with = "parse_option")]
+    pub compressor: Option<TSCompressionType>,
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub tags: Dictionary,
+    #[serde(default)]
+    pub attributes: Dictionary,
+}
+
+fn parse<'de, D, T>(deserializer: D) -> core::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+fn parse_option<'de, D, T>(deserializer: D) -> core::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    parse(deserializer).map(Some)
+}
+
+fn measurement_schema(
+    name: &str,
+    data_type: &TSDataType,
+    encoding: &Option<TSEncoding>,
+    compressor: &Option<TSCompressionType>,
+) -> MeasurementSchema {
+    MeasurementSchema::new(
+        name.to_string(),
+        data_type.clone(),
+        encoding
+            .clone()
+            .unwrap_or_else(|| default_encoding(data_type)),
+        compressor.clone().unwrap_or(DEFAULT_COMPRESSOR),
+        None,
+    )
+}
+
+impl MeasurementSpec {
+    pub fn schema(&self) -> MeasurementSchema {
+        measurement_schema(
+            &self.name,
+            &self.data_type,
+            &self.encoding,
+            &self.compressor,
+        )
+    }
+}
+
+impl TimeseriesSpec {
+    pub fn schema(&self) -> MeasurementSchema {
+        measurement_schema(
+            &self.name,
+            &self.data_type,
+            &self.encoding,
+            &self.compressor,
+        )
+    }
+}
+
+impl TemplateSpec {
+    pub fn template(&self) -> Template {
+        Template::new(
+            &self.name,
+            self.aligned,
+            self.measurements
+                .iter()
+                .map(MeasurementSpec::schema)
+                .collect(),
+        )
+    }
+}
+
+impl SchemaFile {
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let file: SchemaFile = toml::from_str(s)?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    pub fn from_yaml(s: &str) -> Result<Self> {
+        let file: SchemaFile = serde_yaml::from_str(s)?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    /// Loads a `.toml`, `.yaml` or `.yml` file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}, {}", path.display(), err))?;
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        match extension.to_ascii_lowercase().as_str() {
+            "toml" => Self::from_toml(&content),
+            "yaml" | "yml" => Self::from_yaml(&content),
+            _ => Err(format!(
+                "Unknown schema file type '{}', expected .toml, .yaml or .yml",
+                path.display()
+            )
+            .into()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let check_path = |path: &str| -> Result<()> {
+            if path.starts_with("root.") && !path.split('.').any(str::is_empty) {
+                Ok(())
+            } else {
+                Err(format!("Invalid path '{}'", path).into())
+            }
+        };
+        let mut names = BTreeSet::new();
+        for storage_group in &self.storage_groups {
+            check_path(&storage_group.path)?;
+            if !names.insert(&storage_group.path) {
+                return Err(format!("Duplicate storage group '{}'", storage_group.path).into());
+            }
+        }
+        let mut names = BTreeSet::new();
+        for template in &self.templates {
+            if !names.insert(&template.name) {
+                return Err(format!("Duplicate template '{}'", template.name).into());
+            }
+            let mut measurements = BTreeSet::new();
+            for measurement in &template.measurements {
+                if !measurements.insert(&measurement.name) {
+                    return Err(format!(
+                        "Duplicate measurement '{}' in template '{}'",
+                        measurement.name, template.name
+                    )
+                    .into());
+                }
+            }
+            for path in &template.set_on {
+                check_path(path)?;
+            }
+        }
+        let mut paths = BTreeSet::new();
+        let mut devices = BTreeMap::new();
+        for device in &self.devices {
+            check_path(&device.path)?;
+            if devices.insert(&device.path, device.aligned) == Some(!device.aligned) {
+                return Err(format!(
+                    "Device '{}' is declared both aligned and not aligned",
+                    device.path
+                )
+                .into());
+            }
+            for timeseries in &device.timeseries {
+                let path = format!("{}.{}", device.path, timeseries.name);
+                check_path(&path)?;
+                if !paths.insert(path) {
+                    return Err(format!(
+                        "Duplicate timeseries '{}.{}'",
+                        device.path, timeseries.name
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetches the parts of the server schema the file declares and diffs
+    /// them.
+    pub fn plan(&self, session: &mut RpcSession) -> Result<Plan> {
+        let live = LiveSchema::fetch(session, self)?;
+        Ok(self.diff(&live))
+    }
+
+    /// The changes which bring the server to the declared schema.
+    pub fn diff(&self, live: &LiveSchema) -> Plan {
+        let mut plan = Plan::default();
+
+        for storage_group in &self.storage_groups {
+            let live_ttl = live.storage_groups.get(&storage_group.path);
+            if live_ttl.is_none() {
+                plan.changes
+                    .push(Change::SetStorageGroup(storage_group.path.clone()));
+            }
+            if let Some(ttl) = storage_group.ttl {
+                if live_ttl.cloned().flatten() != Some(ttl) {
+                    plan.changes.push(Change::SetTtl {
+                        storage_group: storage_group.path.clone(),
+                        ttl,
+                    });
+                }
+            }
+        }
+
+        for spec in &self.templates {
+            let template = spec.template();
+            match live.templates.get(&spec.name) {
+                None => plan.changes.push(Change::CreateTemplate(template)),
+                Some(schemas) => {
+                    let sorted = |schemas: &[MeasurementSchema]| {
+                        let mut schemas = schemas.to_vec();
+                        schemas.sort();
+                        schemas
+                    };
+                    if sorted(schemas) != sorted(&template.measurement_schemas) {
+                        plan.conflicts.push(format!(
+                            "template {} exists with other measurements [{}]",
+                            spec.name,
+                            describe(schemas)
+                        ));
+                    }
+                }
+            }
+            let set_on = live.template_paths.get(&spec.name);
+            for path in &spec.set_on {
+                if !set_on.is_some_and(|paths| paths.contains(path)) {
+                    plan.changes.push(Change::SetTemplate {
+                        template: spec.name.clone(),
+                        path: path.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut alters = Vec::new();
+        for device in &self.devices {
+            let prefix = format!("{}.", device.path);
+            let exists = live
+                .timeseries
+                .range(prefix.clone()..)
+                .next()
+                .is_some_and(|(path, _)| path.starts_with(&prefix));
+            if exists && live.aligned_devices.contains(&device.path) != device.aligned {
+                plan.conflicts.push(format!(
+                    "device {} exists {}",
+                    device.path,
+                    if device.aligned {
+                        "not aligned"
+                    } else {
+                        "aligned"
+                    }
+                ));
+                continue;
+            }
+
+            let mut missing = Vec::new();
+            for timeseries in &device.timeseries {
+                let path = format!("{}.{}", device.path, timeseries.name);
+                let existing = match live.timeseries.get(&path) {
+                    Some(existing) => existing,
+                    None => {
+                        missing.push(timeseries.clone());
+                        //Aligned timeseries are created without tags and attributes.
+                        if device.aligned
+                            && (!timeseries.tags.is_empty() || !timeseries.attributes.is_empty())
+                        {
+                            alters.push(Change::AlterTimeseries {
+                                path,
+                                alias: None,
+                                tags: timeseries.tags.clone(),
+                                attributes: timeseries.attributes.clone(),
+                            });
+                        }
+                        continue;
+                    }
+                };
+                let schema = timeseries.schema();
+                if (&schema.data_type, &schema.encoding, &schema.compressor)
+                    != (
+                        &existing.schema.data_type,
+                        &existing.schema.encoding,
+                        &existing.schema.compressor,
+                    )
+                {
+                    plan.conflicts.push(format!(
+                        "timeseries {} exists as {}, declared as {}",
+                        path,
+                        describe_type(&existing.schema),
+                        describe_type(&schema)
+                    ));
+                    continue;
+                }
+                let alias = timeseries
+                    .alias
+                    .clone()
+                    .filter(|alias| existing.alias.as_ref() != Some(alias));
+                let changed = |declared: &Dictionary, live: &Dictionary| -> Dictionary {
+                    declared
+                        .iter()
+                        .filter(|(key, value)| live.get(*key) != Some(value))
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect()
+                };
+                let tags = changed(&timeseries.tags, &existing.tags);
+                let attributes = changed(&timeseries.attributes, &existing.attributes);
+                if alias.is_some() || !tags.is_empty() || !attributes.is_empty() {
+                    alters.push(Change::AlterTimeseries {
+                        path,
+                        alias,
+                        tags,
+                        attributes,
+                    });
+                }
+            }
+            if !missing.is_empty() {
+                plan.changes.push(Change::CreateTimeseries {
+                    device: device.path.clone(),
+                    aligned: device.aligned,
+                    timeseries: missing,
+                });
+            }
+        }
+        plan.changes.append(&mut alters);
+        plan
+    }
+}
+
+/// A timeseries as the server reports it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveTimeseries {
+    pub schema: MeasurementSchema,
+    pub alias: Option<String>,
+    pub tags: Dictionary,
+    pub attributes: Dictionary,
+}
+
+/// The server schema a `SchemaFile` is diffed against.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LiveSchema {
+    /// Storage groups with their TTL, `None` if it's not set.
+    pub storage_groups: BTreeMap<String, Option<i64>>,
+    /// Timeseries by full path.
+    pub timeseries: BTreeMap<String, LiveTimeseries>,
+    pub aligned_devices: BTreeSet<String>,
+    pub templates: BTreeMap<String, Vec<MeasurementSchema>>,
+    /// The paths the templates are set on by template name.
+    pub template_paths: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl LiveSchema {
+    /// Runs the SHOW queries for what the file declares.
+    pub fn fetch(session: &mut RpcSession, file: &SchemaFile) -> Result<Self> {
+        let mut live = LiveSchema::default();
+
+        if !file.storage_groups.is_empty() {
+            for row in show(session, "SHOW STORAGE GROUP")? {
+                if let Some(path) = row.get("storage group") {
+                    live.storage_groups.insert(path.clone(), None);
+                }
+            }
+            for row in show(session, "SHOW ALL TTL")? {
+                if let (Some(path), Some(ttl)) = (row.get("storage group"), row.get("ttl")) {
+                    if let Some(storage_group) = live.storage_groups.get_mut(path) {
+                        *storage_group = ttl.parse().ok();
+                    }
+                }
+            }
+        }
+
+        if !file.templates.is_empty() {
+            let names: Vec<String> = show(session, "SHOW SCHEMA TEMPLATES")?
+                .into_iter()
+                .filter_map(|mut row| row.remove("template name"))
+                .collect();
+            for name in names {
+                let mut schemas = Vec::new();
+                let statement = format!("SHOW NODES IN SCHEMA TEMPLATE {}", name);
+                for row in show(session, &statement)? {
+                    schemas.push(MeasurementSchema::new(
+                        column(&row, "child nodes")?.to_string(),
+                        column(&row, "datatype")?.parse()?,
+                        column(&row, "encoding")?.parse()?,
+                        column(&row, "compression")?.parse()?,
+                        None,
+                    ));
+                }
+                let statement = format!("SHOW PATHS SET SCHEMA TEMPLATE {}", name);
+                let paths = show(session, &statement)?
+                    .into_iter()
+                    .filter_map(|mut row| row.remove("child paths"))
+                    .collect();
+                live.templates.insert(name.clone(), schemas);
+                live.template_paths.insert(name, paths);
+            }
+        }
+
+        for device in &file.devices {
+            let statement = format!("SHOW TIMESERIES {}.*", device.path);
+            for row in show(session, &statement)? {
+                let path = column(&row, "timeseries")?.to_string();
+                let schema = MeasurementSchema::new(
+                    path.rsplit('.').next().unwrap_or_default().to_string(),
+                    column(&row, "datatype")?.parse()?,
+                    column(&row, "encoding")?.parse()?,
+                    column(&row, "compression")?.parse()?,
+                    None,
+                );
+                let timeseries = LiveTimeseries {
+                    schema,
+                    alias: row.get("alias").cloned(),
+                    tags: parse_dictionary(row.get("tags"))?,
+                    attributes: parse_dictionary(row.get("attributes"))?,
+                };
+                live.timeseries.insert(path, timeseries);
+            }
+            let statement = format!("SHOW DEVICES {}", device.path);
+            for row in show(session, &statement)? {
+                let aligned = row
+                    .get("isaligned")
+                    .is_some_and(|aligned| aligned.eq_ignore_ascii_case("true"));
+                if let (Some(path), true) = (row.get("devices"), aligned) {
+                    live.aligned_devices.insert(path.clone());
+                }
+            }
+        }
+        Ok(live)
+    }
+}
+
+/// The rows of a SHOW query by lower case column name, nulls are left out.
+fn show(session: &mut RpcSession, statement: &str) -> Result<Vec<BTreeMap<String, String>>> {
+    let data_set = Session::execute_query_statement(&mut *session, statement, None)?;
+    let columns: Vec<String> = data_set
+        .get_column_names()
+        .iter()
+        .map(|column| column.to_ascii_lowercase())
+        .collect();
+    Ok(data_set
+        .map(|row| {
+            columns
+                .iter()
+                .zip(row.values)
+                .filter(|(_, value)| !matches!(value, Value::Null))
+                .map(|(column, value)| (column.clone(), value.to_string()))
+                .filter(|(_, value)| value != "null")
+                .collect()
+        })
+        .collect())
+}
+
+fn column<'r>(row: &'r BTreeMap<String, String>, name: &str) -> Result<&'r str> {
+    row.get(name)
+        .map(String::as_str)
+        .ok_or_else(|| format!("The server returned no {} column", name).into())
+}
+
+/// Parses the JSON object the server shows tags and attributes as.
+fn parse_dictionary(json: Option<&String>) -> Result<Dictionary> {
+    match json {
+        Some(json) if !json.trim().is_empty() => Ok(serde_yaml::from_str(json)?),
+        _ => Ok(Dictionary::new()),
+    }
+}
+
+fn describe_type(schema: &MeasurementSchema) -> String {
+    format!(
+        "{}/{}/{}",
+        schema.data_type, schema.encoding, schema.compressor
+    )
+}
+
+fn describe(schemas: &[MeasurementSchema]) -> String {
+    schemas
+        .iter()
+        .map(|schema| format!("{} {}", schema.measurement, describe_type(schema)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_dictionary(dictionary: &Dictionary) -> String {
+    dictionary
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Quotes a tag or attribute for a statement.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    SetStorageGroup(String),
+    SetTtl {
+        storage_group: String,
+        ttl: i64,
+    },
+    CreateTemplate(Template),
+    SetTemplate {
+        template: String,
+        path: String,
+    },
+    CreateTimeseries {
+        device: String,
+        aligned: bool,
+        timeseries: Vec<TimeseriesSpec>,
+    },
+    /// Upserts the alias, tags and attributes of a timeseries.
+    AlterTimeseries {
+        path: String,
+        alias: Option<String>,
+        tags: Dictionary,
+        attributes: Dictionary,
+    },
+}
+
+impl Change {
+    pub fn apply<S: for<'a> Session<'a>>(&self, session: &mut S) -> Result<()> {
+        match self {
+            Change::SetStorageGroup(path) => session.set_storage_group(path),
+            Change::SetTtl { storage_group, ttl } => {
+                let statement = format!("SET TTL TO {} {}", storage_group, ttl);
+                session.execute_batch_statement(vec![&statement])
+            }
+            Change::CreateTemplate(template) => session.create_schema_template(template),
+            Change::SetTemplate { template, path } => session.set_schema_template(template, path),
+            Change::CreateTimeseries {
+                device,
+                aligned: true,
+                timeseries,
+            } => {
+                let schemas: Vec<_> = timeseries.iter().map(TimeseriesSpec::schema).collect();
+                session.create_aligned_timeseries(
+                    device,
+                    timeseries.iter().map(|spec| spec.name.as_str()).collect(),
+                    schemas.iter().map(|s| s.data_type.clone()).collect(),
+                    schemas.iter().map(|s| s.encoding.clone()).collect(),
+                    schemas.iter().map(|s| s.compressor.clone()).collect(),
+                    aliases(timeseries),
+                )
+            }
+            Change::CreateTimeseries {
+                device,
+                aligned: false,
+                timeseries,
+            } => {
+                let paths: Vec<String> = timeseries
+                    .iter()
+                    .map(|spec| format!("{}.{}", device, spec.name))
+                    .collect();
+                let schemas: Vec<_> = timeseries.iter().map(TimeseriesSpec::schema).collect();
+                let dictionaries = |dictionary: fn(&TimeseriesSpec) -> &Dictionary| {
+                    let dictionaries: Vec<Dictionary> = timeseries
+                        .iter()
+                        .map(|spec| dictionary(spec).clone())
+                        .collect();
+                    Some(dictionaries).filter(|d| d.iter().any(|d| !d.is_empty()))
+                };
+                session.create_multi_timeseries(
+                    paths.iter().map(String::as_str).collect(),
+                    schemas.iter().map(|s| s.data_type.clone()).collect(),
+                    schemas.iter().map(|s| s.encoding.clone()).collect(),
+                    schemas.iter().map(|s| s.compressor.clone()).collect(),
+                    None,
+                    dictionaries(|spec| &spec.attributes),
+                    dictionaries(|spec| &spec.tags),
+                    aliases(timeseries),
+                )
+            }
+            Change::AlterTimeseries {
+                path,
+                alias,
+                tags,
+                attributes,
+            } => {
+                let mut statement = format!("ALTER timeseries {} UPSERT", path);
+                if let Some(alias) = alias {
+                    statement.push_str(&format!(" ALIAS={}", alias));
+                }
+                let pairs = |dictionary: &Dictionary| {
+                    dictionary
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", quote(key), quote(value)))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                if !tags.is_empty() {
+                    statement.push_str(&format!(" TAGS({})", pairs(tags)));
+                }
+                if !attributes.is_empty() {
+                    statement.push_str(&format!(" ATTRIBUTES({})", pairs(attributes)));
+                }
+                session.execute_batch_statement(vec![&statement])
+            }
+        }
+    }
+}
+
+fn aliases(timeseries: &[TimeseriesSpec]) -> Option<Vec<String>> {
+    if timeseries.iter().any(|spec| spec.alias.is_some()) {
+        Some(
+            timeseries
+                .iter()
+                .map(|spec| spec.alias.clone().unwrap_or_default())
+                .collect(),
+        )
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::SetStorageGroup(path) => write!(f, "+ storage group {}", path),
+            Change::SetTtl { storage_group, ttl } => {
+                write!(f, "~ ttl of {} to {}", storage_group, ttl)
+            }
+            Change::CreateTemplate(template) => write!(
+                f,
+                "+ template {}{} [{}]",
+                template.name,
+                if template.is_aligned { " aligned" } else { "" },
+                describe(&template.measurement_schemas)
+            ),
+            Change::SetTemplate { template, path } => {
+                write!(f, "+ template {} on {}", template, path)
+            }
+            Change::CreateTimeseries {
+                device,
+                aligned,
+                timeseries,
+            } => {
+                for (index, spec) in timeseries.iter().enumerate() {
+                    if index > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(
+                        f,
+                        "+ {}timeseries {}.{} {}",
+                        if *aligned { "aligned " } else { "" },
+                        device,
+                        spec.name,
+                        describe_type(&spec.schema())
+                    )?;
+                    if let Some(alias) = &spec.alias {
+                        write!(f, " alias {}", alias)?;
+                    }
+                }
+                Ok(())
+            }
+            Change::AlterTimeseries {
+                path,
+                alias,
+                tags,
+                attributes,
+            } => {
+                write!(f, "~ timeseries {}", path)?;
+                if let Some(alias) = alias {
+                    write!(f, " alias {}", alias)?;
+                }
+                if !tags.is_empty() {
+                    write!(f, " tags {{{}}}", describe_dictionary(tags))?;
+                }
+                if !attributes.is_empty() {
+                    write!(f, " attributes {{{}}}", describe_dictionary(attributes))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The changes of a diff in the order they're applied, and the declarations
+/// the server conflicts with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    pub conflicts: Vec<String>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.conflicts.is_empty()
+    }
+
+    /// Applies the changes in order, it fails without changing anything if
+    /// the plan has conflicts.
+    pub fn apply<S: for<'a> Session<'a>>(&self, session: &mut S) -> Result<()> {
+        if !self.conflicts.is_empty() {
+            return Err(format!(
+                "The schema conflicts with the server, {}",
+                self.conflicts.join("; ")
+            )
+            .into());
+        }
+        for change in &self.changes {
+            change
+                .apply(session)
+                .map_err(|err| format!("Failed to apply '{}', {}", change, err))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes, the server matches the schema.");
+        }
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        for conflict in &self.conflicts {
+            writeln!(f, "! {}", conflict)?;
+        }
+        Ok(())
+    }
+}
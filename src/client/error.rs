@@ -0,0 +1,217 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+//! Error types which can be recovered from the boxed errors of `Result` with
+//! `downcast_ref`.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+use thrift::{TransportError, TransportErrorKind};
+
+use crate::protocal::{TSCompressionType, TSDataType, TSEncoding};
+
+use super::TSProtocolVersion;
+
+/// The server rejected every protocol version the client supports.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IncompatibleVersionError {
+    pub client_version: TSProtocolVersion,
+    pub server_version: TSProtocolVersion,
+    pub message: Option<String>,
+}
+
+impl fmt::Display for IncompatibleVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "incompatible protocol version, client: {:?}, server: {:?}",
+            self.client_version, self.server_version
+        )?;
+        if let Some(message) = &self.message {
+            write!(f, ", {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for IncompatibleVersionError {}
+
+/// Establishing the connection to the server timed out.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TimeoutError {
+    pub message: String,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out, {}", self.message)
+    }
+}
+
+impl Error for TimeoutError {}
+
+/// The server couldn't be reached or the connection to it was lost.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConnectionError {
+    pub message: String,
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ConnectionError {}
+
+/// A record of `insert_records` or a tablet of `insert_tablets` which the
+/// server rejected.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BatchFailure {
+    /// Position in the batch passed to the insert.
+    pub index: usize,
+    pub device: String,
+    /// Timestamp of the record, it's `None` for tablets.
+    pub timestamp: Option<i64>,
+    /// Rows of the tablet which may not have been written, the rows outside
+    /// were. It's `None` for records.
+    pub rows: Option<Range<usize>>,
+    pub code: i32,
+    pub message: Option<String>,
+}
+
+/// Some items of a batch insert failed, the others were written and don't
+/// have to be retried.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BatchError {
+    pub failures: Vec<BatchFailure>,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} items of the batch failed", self.failures.len())?;
+        for failure in &self.failures {
+            write!(
+                f,
+                "; index: {}, device: {}, code: {}",
+                failure.index, failure.device, failure.code
+            )?;
+            if let Some(timestamp) = failure.timestamp {
+                write!(f, ", timestamp: {}", timestamp)?;
+            }
+            if let Some(rows) = &failure.rows {
+                write!(f, ", rows: {}..{}", rows.start, rows.end)?;
+            }
+            if let Some(message) = &failure.message {
+                write!(f, ", {}", message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for BatchError {}
+
+/// Returns `true` if the error is caused by a connect, read or write timeout.
+pub fn is_timeout(err: &(dyn Error + 'static)) -> bool {
+    if err.is::<TimeoutError>() {
+        return true;
+    }
+    if let Some(err) = err.downcast_ref::<io::Error>() {
+        return err.kind() == io::ErrorKind::TimedOut;
+    }
+    matches!(
+        err.downcast_ref::<thrift::Error>(),
+        Some(thrift::Error::Transport(TransportError {
+            kind: TransportErrorKind::TimedOut,
+            ..
+        }))
+    )
+}
+
+/// Whether the error means the server couldn't be reached, as opposed to the
+/// server rejecting the request.
+pub fn is_connection_error(err: &(dyn Error + 'static)) -> bool {
+    is_timeout(err)
+        || err.is::<ConnectionError>()
+        || err.is::<io::Error>()
+        || matches!(
+            err.downcast_ref::<thrift::Error>(),
+            Some(thrift::Error::Transport(_))
+        )
+}
+
+/// The queue of a `WriterHandle` is full and its policy is
+/// `OverflowPolicy::Error`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QueueFullError {
+    pub capacity: usize,
+}
+
+impl fmt::Display for QueueFullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "writer queue is full, capacity: {}", self.capacity)
+    }
+}
+
+impl Error for QueueFullError {}
+
+/// Appending to a `Spool` would exceed its `max_bytes`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpoolFullError {
+    pub max_bytes: u64,
+}
+
+impl fmt::Display for SpoolFullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "spool is full, max bytes: {}", self.max_bytes)
+    }
+}
+
+impl Error for SpoolFullError {}
+
+/// A timeseries exists with another data type, encoding or compressor than
+/// the one it was ensured with.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SchemaMismatchError {
+    pub path: String,
+    pub expected: (TSDataType, TSEncoding, TSCompressionType),
+    pub actual: (TSDataType, TSEncoding, TSCompressionType),
+}
+
+impl fmt::Display for SchemaMismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "timeseries {} exists as {}/{}/{}, expected {}/{}/{}",
+            self.path,
+            self.actual.0,
+            self.actual.1,
+            self.actual.2,
+            self.expected.0,
+            self.expected.1,
+            self.expected.2
+        )
+    }
+}
+
+impl Error for SchemaMismatchError {}
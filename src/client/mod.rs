@@ -17,12 +17,31 @@
 // under the License.
 //
 
+pub mod credential;
+#[cfg(feature = "declarative")]
+pub mod declarative;
+pub mod error;
 pub mod remote;
-mod rpc;
+pub(crate) mod rpc;
+pub mod schema;
+pub mod spool;
+pub mod time;
+pub mod tls;
+pub mod transport;
+pub mod writer;
+
+pub use rpc::{EndPoint, TSProtocolVersion};
 
 use crate::protocal::{TSCompressionType, TSDataType, TSEncoding};
 use std::collections::BTreeMap;
 use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use time::{IntoTimestamp, Timestamp, TimestampPrecision};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, TimeZone};
 
 macro_rules! to_be_vec {
     ($v:expr) => {{
@@ -66,12 +85,56 @@ impl MeasurementSchema {
         }
     }
 }
+
+/// A flat schema template, its measurements are created on the devices
+/// below the paths it's set on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub is_aligned: bool,
+    pub measurement_schemas: Vec<MeasurementSchema>,
+}
+
+impl Template {
+    pub fn new(name: &str, is_aligned: bool, measurement_schemas: Vec<MeasurementSchema>) -> Self {
+        Self {
+            name: name.to_string(),
+            is_aligned,
+            measurement_schemas,
+        }
+    }
+}
+
+impl From<&Template> for Vec<u8> {
+    fn from(template: &Template) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::new();
+        let push_str = |buffer: &mut Vec<u8>, s: &str| {
+            buffer.append(&mut to_be_vec!(s.len() as i32));
+            buffer.extend_from_slice(s.as_bytes());
+        };
+        push_str(&mut buffer, &template.name);
+        buffer.push(template.is_aligned as u8);
+        template.measurement_schemas.iter().for_each(|schema| {
+            //The prefix of the measurement, empty for flat templates.
+            push_str(&mut buffer, "");
+            buffer.push(template.is_aligned as u8);
+            push_str(&mut buffer, &schema.measurement);
+            buffer.push(i32::from(schema.data_type.clone()) as u8);
+            buffer.push(i32::from(schema.encoding.clone()) as u8);
+            buffer.push(i32::from(schema.compressor.clone()) as u8);
+        });
+        buffer
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Tablet {
     prefix_path: String,
     measurement_schemas: Vec<MeasurementSchema>,
     timestamps: Vec<i64>,
     columns: Vec<Vec<Value>>,
+    timestamp_precision: TimestampPrecision,
+    is_aligned: bool,
     // bitmaps: Vec<Vec<u8>>,
 }
 
@@ -112,6 +175,8 @@ impl Tablet {
             timestamps: Vec::new(),
             columns,
             measurement_schemas,
+            timestamp_precision: TimestampPrecision::Millisecond,
+            is_aligned: false,
         }
     }
 
@@ -132,7 +197,30 @@ impl Tablet {
         self.measurement_schemas.clone()
     }
 
-    pub fn add_row(&mut self, row: Vec<Value>, timestamp: i64) -> Result<()> {
+    pub fn get_timestamp_precision(&self) -> TimestampPrecision {
+        self.timestamp_precision
+    }
+
+    /// Sets the precision the timestamps passed to `add_row` are kept in,
+    /// plain `i64` timestamps are taken as this precision. They're scaled to
+    /// the server's precision when the tablet is written.
+    pub fn set_timestamp_precision(&mut self, timestamp_precision: TimestampPrecision) {
+        self.timestamp_precision = timestamp_precision;
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.is_aligned
+    }
+
+    /// Writes the tablet to aligned timeseries.
+    pub fn set_aligned(&mut self, is_aligned: bool) {
+        self.is_aligned = is_aligned;
+    }
+
+    pub fn add_row<T>(&mut self, row: Vec<Value>, timestamp: T) -> Result<()>
+    where
+        T: IntoTimestamp,
+    {
         if row.len() != self.columns.len() {
             return Err(format!("row values '{:?}' must match columns", row).into());
         }
@@ -144,7 +232,8 @@ impl Tablet {
             )
         });
 
-        self.timestamps.push(timestamp);
+        self.timestamps
+            .push(timestamp.into_timestamp(self.timestamp_precision));
         self.columns
             .iter_mut()
             .zip(row.iter())
@@ -170,6 +259,64 @@ impl Tablet {
     pub fn get_column_count(&self) -> usize {
         self.columns.len()
     }
+
+    /// The timestamps in the precision as the big-endian bytes of a request.
+    pub(crate) fn timestamps_bytes(&self, precision: TimestampPrecision) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(self.timestamps.len() * 8);
+        for &timestamp in &self.timestamps {
+            let timestamp = Timestamp::new(timestamp, self.timestamp_precision);
+            buffer.extend_from_slice(&timestamp.into_timestamp(precision).to_be_bytes());
+        }
+        buffer
+    }
+
+    /// Estimated size of the prefix path and measurements in a request.
+    pub(crate) fn header_size(&self) -> usize {
+        self.prefix_path.len()
+            + self
+                .measurement_schemas
+                .iter()
+                .map(|schema| schema.measurement.len() + 8)
+                .sum::<usize>()
+    }
+
+    /// Size of the timestamp and values of the row in a request.
+    pub(crate) fn row_size(&self, row_index: usize) -> usize {
+        8 + self
+            .columns
+            .iter()
+            .map(|column| match &column[row_index] {
+                Value::Bool(_) => 1,
+                Value::Int32(_) | Value::Float(_) => 4,
+                Value::Int64(_) | Value::Double(_) => 8,
+                Value::Text(t) => 4 + t.len(),
+                Value::Null => 0,
+            })
+            .sum::<usize>()
+    }
+
+    pub(crate) fn estimated_size(&self) -> usize {
+        self.header_size()
+            + (0..self.get_row_count())
+                .map(|row_index| self.row_size(row_index))
+                .sum::<usize>()
+    }
+
+    /// Copies the rows into a new tablet of the same device and measurements.
+    pub(crate) fn slice(&self, rows: Range<usize>) -> Tablet {
+        Self {
+            prefix_path: self.prefix_path.clone(),
+            measurement_schemas: self.measurement_schemas.clone(),
+            timestamps: self.timestamps[rows.clone()].to_vec(),
+            columns: self
+                .columns
+                .iter()
+                .map(|column| column[rows.clone()].to_vec())
+                .collect(),
+            timestamp_precision: self.timestamp_precision,
+            is_aligned: self.is_aligned,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -183,6 +330,21 @@ pub enum Value {
     Null,
 }
 
+impl Value {
+    /// The data type of the value, `None` for `Null`.
+    pub fn data_type(&self) -> Option<TSDataType> {
+        match self {
+            Value::Bool(_) => Some(TSDataType::Boolean),
+            Value::Int32(_) => Some(TSDataType::Int32),
+            Value::Int64(_) => Some(TSDataType::Int64),
+            Value::Float(_) => Some(TSDataType::Float),
+            Value::Double(_) => Some(TSDataType::Double),
+            Value::Text(_) => Some(TSDataType::Text),
+            Value::Null => None,
+        }
+    }
+}
+
 impl ToString for Value {
     fn to_string(&self) -> String {
         match &self {
@@ -260,6 +422,125 @@ pub struct RowRecord {
     pub timestamp: i64,
     pub values: Vec<Value>,
 }
+
+#[cfg(feature = "chrono")]
+impl RowRecord {
+    pub fn get_date_time<Tz: TimeZone>(
+        &self,
+        precision: TimestampPrecision,
+        tz: &Tz,
+    ) -> Option<DateTime<Tz>> {
+        time::to_date_time(self.timestamp, precision, tz)
+    }
+}
+
+/// Properties reported by the server through `get_properties` on `open`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ServerProperties {
+    pub version: String,
+    pub supported_time_aggregation_operations: Vec<String>,
+    pub timestamp_precision: TimestampPrecision,
+    pub max_concurrent_client_num: Option<i32>,
+    pub thrift_max_frame_size: Option<i32>,
+    pub is_read_only: Option<bool>,
+}
+
+/// The semantic version of a server, pre-release suffixes like `-SNAPSHOT` are ignored.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let core = s.split(['-', '+']).next().unwrap_or_default();
+        let mut numbers = core.split('.').map(|n| n.trim().parse::<u32>());
+        match (numbers.next(), numbers.next(), numbers.next()) {
+            (Some(Ok(major)), minor, patch) => Ok(Self::new(
+                major,
+                minor.and_then(|n| n.ok()).unwrap_or(0),
+                patch.and_then(|n| n.ok()).unwrap_or(0),
+            )),
+            _ => Err(format!("Illegal version '{}'", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Typed view of the `ServerProperties` with capability checks.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ServerInfo {
+    version: Option<Version>,
+    properties: ServerProperties,
+}
+
+impl ServerInfo {
+    /// The first version supporting aligned timeseries.
+    pub const ALIGNED_TIMESERIES_VERSION: Version = Version::new(0, 13, 0);
+
+    /// The parsed server version, `None` if it isn't a semantic version.
+    pub fn version(&self) -> Option<Version> {
+        self.version
+    }
+
+    pub fn properties(&self) -> &ServerProperties {
+        &self.properties
+    }
+
+    pub fn timestamp_precision(&self) -> TimestampPrecision {
+        self.properties.timestamp_precision
+    }
+
+    pub fn supports_aggregation(&self, function: &str) -> bool {
+        self.properties
+            .supported_time_aggregation_operations
+            .iter()
+            .any(|f| f.eq_ignore_ascii_case(function))
+    }
+
+    /// Servers of an unknown version aren't assumed to lack the support.
+    pub fn supports_aligned_timeseries(&self) -> bool {
+        self.version
+            .is_none_or(|version| version >= Self::ALIGNED_TIMESERIES_VERSION)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.properties.is_read_only.unwrap_or(false)
+    }
+
+    pub fn max_frame_size(&self) -> Option<i32> {
+        self.properties.thrift_max_frame_size
+    }
+}
+
+impl From<ServerProperties> for ServerInfo {
+    fn from(properties: ServerProperties) -> Self {
+        Self {
+            version: properties.version.parse().ok(),
+            properties,
+        }
+    }
+}
+
 pub trait DataSet: Iterator<Item = RowRecord> {
     fn get_column_names(&self) -> Vec<String>;
     fn get_data_types(&self) -> Vec<TSDataType>;
@@ -271,6 +552,24 @@ pub trait Session<'a> {
 
     fn close(&mut self) -> Result<()>;
 
+    /// Returns the properties fetched on `open`, `None` if the session isn't opened
+    /// or the server doesn't support `get_properties`.
+    fn server_properties(&self) -> Option<&ServerProperties> {
+        self.server_info().map(ServerInfo::properties)
+    }
+
+    fn server_info(&self) -> Option<&ServerInfo>;
+
+    /// Returns the protocol version negotiated on `open`.
+    fn protocol_version(&self) -> Option<TSProtocolVersion>;
+
+    /// Returns the session configuration returned by the server on `open`.
+    fn server_configuration(&self) -> Option<&Dictionary>;
+
+    /// Returns the policy timeseries are created with, `None` if writes
+    /// don't create them.
+    fn auto_schema(&self) -> Option<&schema::AutoSchemaConfig>;
+
     fn set_storage_group(&mut self, storage_group_id: &str) -> Result<()>;
 
     fn delete_storage_group(&mut self, storage_group_id: &str) -> Result<()>;
@@ -305,25 +604,102 @@ pub trait Session<'a> {
     where
         T: Into<Option<Vec<Dictionary>>>;
 
+    /// Sets the storage group unless it exists, it fails if a storage group
+    /// above or below it exists.
+    fn ensure_storage_group(&mut self, storage_group_id: &str) -> Result<()>;
+
+    /// Creates the timeseries unless it exists, it fails with a
+    /// `SchemaMismatchError` if it exists with another data type, encoding
+    /// or compressor.
+    #[allow(clippy::too_many_arguments)]
+    fn ensure_timeseries<T>(
+        &mut self,
+        path: &str,
+        data_type: TSDataType,
+        encoding: TSEncoding,
+        compressor: TSCompressionType,
+        props: T,
+        attributes: T,
+        tags: T,
+        measurement_alias: Option<String>,
+    ) -> Result<()>
+    where
+        T: Into<Option<Dictionary>>;
+
+    /// Creates the missing timeseries, see `ensure_timeseries`.
+    #[allow(clippy::too_many_arguments)]
+    fn ensure_multi_timeseries<T>(
+        &mut self,
+        paths: Vec<&str>,
+        data_types: Vec<TSDataType>,
+        encodings: Vec<TSEncoding>,
+        compressors: Vec<TSCompressionType>,
+        props_list: T,
+        attributes_list: T,
+        tags_list: T,
+        measurement_alias_list: Option<Vec<String>>,
+    ) -> Result<()>
+    where
+        T: Into<Option<Vec<Dictionary>>>;
+
+    /// Creates the measurements of an aligned device.
+    fn create_aligned_timeseries(
+        &mut self,
+        prefix_path: &str,
+        measurements: Vec<&str>,
+        data_types: Vec<TSDataType>,
+        encodings: Vec<TSEncoding>,
+        compressors: Vec<TSCompressionType>,
+        measurement_alias_list: Option<Vec<String>>,
+    ) -> Result<()>;
+
+    fn create_schema_template(&mut self, template: &Template) -> Result<()>;
+
+    fn set_schema_template(&mut self, template_name: &str, prefix_path: &str) -> Result<()>;
+
+    fn unset_schema_template(&mut self, template_name: &str, prefix_path: &str) -> Result<()>;
+
     fn delete_timeseries(&mut self, paths: Vec<&str>) -> Result<()>;
 
-    fn delete_data(&mut self, paths: Vec<&str>, start_time: i64, end_time: i64) -> Result<()>;
+    fn delete_data<T>(&mut self, paths: Vec<&str>, start_time: T, end_time: T) -> Result<()>
+    where
+        T: IntoTimestamp;
 
-    fn insert_string_record<T>(
+    fn insert_string_record<T, S>(
         &mut self,
         device_id: &str,
         measurements: Vec<&str>,
         values: Vec<&str>,
-        timestamp: i64,
+        timestamp: S,
+        is_aligned: T,
+    ) -> Result<()>
+    where
+        T: Into<Option<bool>>,
+        S: IntoTimestamp;
+
+    /// Inserts records of several devices with textual values, the server
+    /// infers their data types.
+    fn insert_string_records<T, S>(
+        &mut self,
+        prefix_paths: Vec<&str>,
+        measurements: Vec<Vec<&str>>,
+        values: Vec<Vec<&str>>,
+        timestamps: Vec<S>,
         is_aligned: T,
     ) -> Result<()>
     where
-        T: Into<Option<bool>>;
+        T: Into<Option<bool>>,
+        S: IntoTimestamp;
 
     fn get_time_zone(&mut self) -> Result<String>;
 
     fn set_time_zone(&mut self, time_zone: &str) -> Result<()>;
 
+    #[cfg(feature = "chrono")]
+    fn get_chrono_time_zone(&mut self) -> Result<chrono_tz::Tz> {
+        time::parse_time_zone(&self.get_time_zone()?)
+    }
+
     fn execute_statement<T>(
         &'a mut self,
         statement: &str,
@@ -340,33 +716,57 @@ pub trait Session<'a> {
     where
         T: Into<Option<i64>>;
 
-    fn insert_record<T>(
+    /// Null values are skipped with their measurements. A record without
+    /// any other value isn't sent and `Ok(())` is returned, like the fully
+    /// null records of `insert_records`.
+    fn insert_record<T, S>(
         &mut self,
         device_id: &str,
         measurements: Vec<&str>,
         values: Vec<Value>,
-        timestamp: i64,
+        timestamp: S,
         is_aligned: T,
     ) -> Result<()>
     where
-        T: Into<Option<bool>>;
+        T: Into<Option<bool>>,
+        S: IntoTimestamp;
 
-    fn insert_records_of_one_device(
+    /// Null values are skipped as by `insert_record`.
+    fn insert_records_of_one_device<S>(
         &mut self,
         device_id: &str,
-        timestamps: Vec<i64>,
+        timestamps: Vec<S>,
         measurements: Vec<Vec<&str>>,
         values: Vec<Vec<Value>>,
         sorted: bool,
-    ) -> Result<()>;
+    ) -> Result<()>
+    where
+        S: IntoTimestamp;
+
+    /// Inserts the records into the aligned timeseries of the device, nulls
+    /// are skipped as by `insert_record`.
+    fn insert_aligned_records_of_one_device<S>(
+        &mut self,
+        device_id: &str,
+        timestamps: Vec<S>,
+        measurements: Vec<Vec<&str>>,
+        values: Vec<Vec<Value>>,
+        sorted: bool,
+    ) -> Result<()>
+    where
+        S: IntoTimestamp;
 
-    fn insert_records(
+    /// Null values are skipped as by `insert_record`, the fully null records
+    /// aren't sent and don't fail the batch.
+    fn insert_records<S>(
         &mut self,
         prefix_path: Vec<&str>,
         measurements: Vec<Vec<&str>>,
         values: Vec<Vec<Value>>,
-        timestamps: Vec<i64>,
-    ) -> Result<()>;
+        timestamps: Vec<S>,
+    ) -> Result<()>
+    where
+        S: IntoTimestamp;
 
     fn insert_tablet(&mut self, tablet: &Tablet) -> Result<()>;
 
@@ -374,12 +774,23 @@ pub trait Session<'a> {
 
     fn execute_batch_statement(&mut self, statemens: Vec<&str>) -> Result<()>;
 
-    fn execute_raw_data_query(
+    fn execute_raw_data_query<T>(
+        &'a mut self,
+        paths: Vec<&str>,
+        start_time: T,
+        end_time: T,
+    ) -> Result<Box<dyn 'a + DataSet>>
+    where
+        T: IntoTimestamp;
+
+    /// Queries the latest points of the paths which are newer than `last_time`.
+    fn execute_last_data_query<T>(
         &'a mut self,
         paths: Vec<&str>,
-        start_time: i64,
-        end_time: i64,
-    ) -> Result<Box<dyn 'a + DataSet>>;
+        last_time: T,
+    ) -> Result<Box<dyn 'a + DataSet>>
+    where
+        T: IntoTimestamp;
 
     fn execute_update_statement(
         &'a mut self,
@@ -0,0 +1,270 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use typed_builder::TypedBuilder;
+
+use crate::protocal::{
+    TSCompressionType, TSDataType, TSEncoding, METADATA_ERROR, MULTIPLE_ERROR,
+    PATH_NOT_EXIST_ERROR, TIMESERIES_NOT_EXIST,
+};
+
+use super::rpc::TSStatus;
+use super::{MeasurementSchema, Result, Tablet, Value};
+
+/// How the timeseries missing for a write are created when
+/// `Config::auto_schema` is set.
+///
+/// The data type is inferred from the written `Value`s, or taken from the
+/// schemas of a `Tablet`, which also provide the encoding and compressor.
+/// String records are left to the server since their types are unknown.
+#[derive(Debug, Clone, TypedBuilder)]
+#[builder(field_defaults(default, setter(into)))]
+pub struct AutoSchemaConfig {
+    /// Number of nodes below `root` which make up the storage group of a
+    /// device, e.g. 1 for `root.sg` of `root.sg.dev`.
+    #[builder(default = 1)]
+    pub storage_group_level: usize,
+    /// Overrides the encoding of a data type, see `default_encoding`.
+    pub encodings: HashMap<TSDataType, TSEncoding>,
+    /// Overrides the compressor of a data type.
+    pub compressors: HashMap<TSDataType, TSCompressionType>,
+    /// Compressor of the data types without an override.
+    #[builder(default = TSCompressionType::SNAPPY)]
+    pub compressor: TSCompressionType,
+}
+
+impl Default for AutoSchemaConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl AutoSchemaConfig {
+    pub fn encoding(&self, data_type: &TSDataType) -> TSEncoding {
+        self.encodings
+            .get(data_type)
+            .cloned()
+            .unwrap_or_else(|| default_encoding(data_type))
+    }
+
+    pub fn compressor(&self, data_type: &TSDataType) -> TSCompressionType {
+        self.compressors
+            .get(data_type)
+            .cloned()
+            .unwrap_or_else(|| self.compressor.clone())
+    }
+
+    /// The storage group a device belongs to.
+    pub fn storage_group(&self, device_id: &str) -> Result<String> {
+        let nodes: Vec<&str> = device_id.split('.').collect();
+        if nodes.len() <= self.storage_group_level || nodes[0] != "root" {
+            return Err(format!(
+                "Device '{}' has no storage group at level {}",
+                device_id, self.storage_group_level
+            )
+            .into());
+        }
+        Ok(nodes[..=self.storage_group_level].join("."))
+    }
+
+    /// The schema of a measurement holding `value`, `None` for nulls.
+    pub(crate) fn schema_of(&self, measurement: &str, value: &Value) -> Option<MeasurementSchema> {
+        value.data_type().map(|data_type| {
+            MeasurementSchema::new(
+                measurement.to_string(),
+                data_type.clone(),
+                self.encoding(&data_type),
+                self.compressor(&data_type),
+                None,
+            )
+        })
+    }
+}
+
+/// The encoding the server uses by default for a data type.
+pub fn default_encoding(data_type: &TSDataType) -> TSEncoding {
+    match data_type {
+        TSDataType::Boolean | TSDataType::Int32 | TSDataType::Int64 => TSEncoding::RLE,
+        TSDataType::Float | TSDataType::Double => TSEncoding::Gorilla,
+        TSDataType::Text => TSEncoding::Plain,
+    }
+}
+
+/// The timeseries a write needs by device, the first schema of a
+/// measurement wins.
+#[derive(Debug, Default)]
+pub(crate) struct WriteSchema(BTreeMap<String, Vec<MeasurementSchema>>);
+
+impl WriteSchema {
+    pub(crate) fn add(&mut self, device_id: &str, schema: MeasurementSchema) {
+        let schemas = self.0.entry(device_id.to_string()).or_default();
+        if schemas.iter().all(|s| s.measurement != schema.measurement) {
+            schemas.push(schema);
+        }
+    }
+
+    pub(crate) fn add_record(
+        &mut self,
+        config: &AutoSchemaConfig,
+        device_id: &str,
+        measurements: &[&str],
+        values: &[Value],
+    ) {
+        for (measurement, value) in measurements.iter().zip(values) {
+            if let Some(schema) = config.schema_of(measurement, value) {
+                self.add(device_id, schema);
+            }
+        }
+    }
+
+    pub(crate) fn add_tablet(&mut self, tablet: &Tablet) {
+        for schema in &tablet.measurement_schemas {
+            self.add(&tablet.prefix_path, schema.clone());
+        }
+    }
+
+    pub(crate) fn devices(&self) -> impl Iterator<Item = (&String, &Vec<MeasurementSchema>)> {
+        self.0.iter()
+    }
+}
+
+/// Whether the server rejected a write, or some of a batch, because
+/// timeseries don't exist.
+pub(crate) fn is_schema_missing(status: &TSStatus) -> bool {
+    let missing = |code| code == TIMESERIES_NOT_EXIST || code == PATH_NOT_EXIST_ERROR;
+    match &status.sub_status {
+        Some(sub_status) if status.code == MULTIPLE_ERROR => {
+            sub_status.iter().any(|sub| missing(sub.code))
+        }
+        _ => missing(status.code),
+    }
+}
+
+/// Whether the server rejected a write, or some of a batch, because of the
+/// schema, so the cached schemas of its devices may be stale.
+pub(crate) fn is_schema_error(status: &TSStatus) -> bool {
+    let schema_error = |code| {
+        code == TIMESERIES_NOT_EXIST || code == PATH_NOT_EXIST_ERROR || code == METADATA_ERROR
+    };
+    match &status.sub_status {
+        Some(sub_status) if status.code == MULTIPLE_ERROR => {
+            sub_status.iter().any(|sub| schema_error(sub.code))
+        }
+        _ => schema_error(status.code),
+    }
+}
+
+#[derive(Debug, Clone, TypedBuilder)]
+#[builder(field_defaults(default, setter(into)))]
+pub struct SchemaCacheConfig {
+    /// Maximum number of cached timeseries, the oldest are evicted first.
+    #[builder(default = 10000)]
+    pub max_entries: usize,
+    /// How long a schema is trusted after it was fetched or created.
+    #[builder(default = Duration::from_secs(600))]
+    pub ttl: Duration,
+}
+
+impl Default for SchemaCacheConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// The schemas of timeseries by full path, it's filled by
+/// `RpcSession::fetch_timeseries` and the successful creates of the session,
+/// and the devices are dropped when the server reports schema errors for them.
+#[derive(Debug)]
+pub struct SchemaCache {
+    config: SchemaCacheConfig,
+    entries: HashMap<String, (MeasurementSchema, Instant)>,
+    /// Paths in insertion order, an entry is only evicted if its time matches.
+    order: VecDeque<(String, Instant)>,
+}
+
+impl SchemaCache {
+    pub fn new(config: SchemaCacheConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The schema of the timeseries unless it's unknown or expired.
+    pub fn get(&self, path: &str) -> Option<&MeasurementSchema> {
+        match self.entries.get(path) {
+            Some((schema, cached_at)) if cached_at.elapsed() < self.config.ttl => Some(schema),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, path: &str, schema: MeasurementSchema) {
+        if self.config.max_entries == 0 {
+            return;
+        }
+        while self.entries.len() >= self.config.max_entries && !self.entries.contains_key(path) {
+            match self.order.pop_front() {
+                Some((oldest, cached_at)) => {
+                    if self.entries.get(&oldest).map(|(_, at)| *at) == Some(cached_at) {
+                        self.entries.remove(&oldest);
+                    }
+                }
+                None => break,
+            }
+        }
+        let now = Instant::now();
+        self.entries.insert(path.to_string(), (schema, now));
+        self.order.push_back((path.to_string(), now));
+        //Re-inserted paths leave stale entries in `order`, compact them.
+        if self.order.len() > 2 * self.config.max_entries {
+            let entries = &self.entries;
+            self.order
+                .retain(|(path, at)| entries.get(path).map(|(_, cached_at)| cached_at) == Some(at));
+        }
+    }
+
+    /// Drops the timeseries at or below the path, e.g. of a device or a
+    /// storage group, a pattern drops everything below its first wildcard.
+    pub fn invalidate(&mut self, path: &str) {
+        let path = match path.find('*') {
+            Some(index) => path[..index].trim_end_matches('.'),
+            None => path,
+        };
+        let prefix = format!("{}.", path);
+        self.entries
+            .retain(|cached, _| cached != path && !cached.starts_with(&prefix));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
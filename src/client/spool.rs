@@ -0,0 +1,527 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+//! A local write-ahead spool which keeps the batches written while the
+//! server is unreachable and replays them in order once it's back.
+//!
+//! The batches are appended to segment files in a directory, each entry is
+//! framed with a magic number, its length and a CRC32, so a torn or corrupted
+//! entry is skipped without losing the rest of the segment. A segment is
+//! deleted once all of its entries were written, a replay interrupted by a
+//! connection error resumes at the first entry which wasn't written, or at the
+//! start of the segment after a restart, so batches are written at least once.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use typed_builder::TypedBuilder;
+
+use crate::protocal::{TSCompressionType, TSDataType, TSEncoding};
+
+use super::error::{is_connection_error, SpoolFullError};
+use super::time::TimestampPrecision;
+use super::{MeasurementSchema, Result, Session, Tablet, Value};
+
+const MAGIC: [u8; 4] = *b"IDBS";
+const ENTRY_HEADER_SIZE: usize = 12;
+const SEGMENT_EXTENSION: &str = "spool";
+
+const TABLET_BATCH: u8 = 1;
+const RECORDS_BATCH: u8 = 2;
+const NULL_VALUE: u8 = 0xff;
+
+#[derive(Debug, Clone, TypedBuilder)]
+#[builder(field_defaults(default, setter(into)))]
+pub struct SpoolConfig {
+    /// Directory of the segment files, it's created if it doesn't exist.
+    #[builder(!default)]
+    pub dir: PathBuf,
+    /// Maximum size of all segments, appending beyond it fails with a
+    /// `SpoolFullError`.
+    #[builder(default = 1024 * 1024 * 1024)]
+    pub max_bytes: u64,
+    /// A new segment is started once the current one reaches this size.
+    #[builder(default = 64 * 1024 * 1024)]
+    pub segment_bytes: u64,
+    /// Syncs every appended entry to disk.
+    #[builder(default = true)]
+    pub sync: bool,
+}
+
+/// A batch of points, the timestamps of records are in the precision of the
+/// server and those of a tablet in the tablet's own precision.
+#[derive(Debug, Clone)]
+pub enum Batch {
+    Tablet(Tablet),
+    Records {
+        prefix_paths: Vec<String>,
+        measurements: Vec<Vec<String>>,
+        values: Vec<Vec<Value>>,
+        timestamps: Vec<i64>,
+    },
+}
+
+impl Batch {
+    fn send<S>(&self, session: &mut S) -> Result<()>
+    where
+        S: for<'a> Session<'a>,
+    {
+        match self {
+            Batch::Tablet(tablet) => session.insert_tablet(tablet),
+            Batch::Records {
+                prefix_paths,
+                measurements,
+                values,
+                timestamps,
+            } => session.insert_records(
+                prefix_paths.iter().map(String::as_str).collect(),
+                measurements
+                    .iter()
+                    .map(|row| row.iter().map(String::as_str).collect())
+                    .collect(),
+                values.clone(),
+                timestamps.clone(),
+            ),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        match self {
+            Batch::Tablet(tablet) => {
+                buffer.push(TABLET_BATCH);
+                put_str(&mut buffer, &tablet.prefix_path);
+                buffer.push(match tablet.timestamp_precision {
+                    TimestampPrecision::Millisecond => 0,
+                    TimestampPrecision::Microsecond => 1,
+                    TimestampPrecision::Nanosecond => 2,
+                });
+                buffer.push(tablet.is_aligned as u8);
+                put_len(&mut buffer, tablet.measurement_schemas.len());
+                for schema in &tablet.measurement_schemas {
+                    put_str(&mut buffer, &schema.measurement);
+                    buffer.push(i32::from(schema.data_type.clone()) as u8);
+                    buffer.push(i32::from(schema.encoding.clone()) as u8);
+                    buffer.push(i32::from(schema.compressor.clone()) as u8);
+                }
+                put_len(&mut buffer, tablet.timestamps.len());
+                for timestamp in &tablet.timestamps {
+                    buffer.extend_from_slice(&timestamp.to_be_bytes());
+                }
+                for value in tablet.columns.iter().flatten() {
+                    put_value(&mut buffer, value);
+                }
+            }
+            Batch::Records {
+                prefix_paths,
+                measurements,
+                values,
+                timestamps,
+            } => {
+                buffer.push(RECORDS_BATCH);
+                put_len(&mut buffer, prefix_paths.len());
+                for (((prefix_path, measurements), values), timestamp) in prefix_paths
+                    .iter()
+                    .zip(measurements)
+                    .zip(values)
+                    .zip(timestamps)
+                {
+                    put_str(&mut buffer, prefix_path);
+                    buffer.extend_from_slice(&timestamp.to_be_bytes());
+                    put_len(&mut buffer, measurements.len());
+                    for (measurement, value) in measurements.iter().zip(values) {
+                        put_str(&mut buffer, measurement);
+                        put_value(&mut buffer, value);
+                    }
+                }
+            }
+        }
+        buffer
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader(bytes);
+        let batch = match reader.u8()? {
+            TABLET_BATCH => {
+                let prefix_path = reader.string()?;
+                let timestamp_precision = match reader.u8()? {
+                    0 => TimestampPrecision::Millisecond,
+                    1 => TimestampPrecision::Microsecond,
+                    2 => TimestampPrecision::Nanosecond,
+                    precision => return Err(format!("unknown precision {}", precision).into()),
+                };
+                let is_aligned = reader.u8()? != 0;
+                let mut schemas = Vec::new();
+                for _ in 0..reader.u32()? {
+                    schemas.push(MeasurementSchema::new(
+                        reader.string()?,
+                        TSDataType::try_from(reader.u8()? as i32)?,
+                        TSEncoding::try_from(reader.u8()? as i32)?,
+                        TSCompressionType::try_from(reader.u8()? as i32)?,
+                        None,
+                    ));
+                }
+                let rows = reader.u32()? as usize;
+                let mut timestamps = Vec::new();
+                for _ in 0..rows {
+                    timestamps.push(reader.i64()?);
+                }
+                let mut columns = Vec::new();
+                for _ in 0..schemas.len() {
+                    let mut column = Vec::new();
+                    for _ in 0..rows {
+                        column.push(reader.value()?);
+                    }
+                    columns.push(column);
+                }
+                Batch::Tablet(Tablet {
+                    prefix_path,
+                    measurement_schemas: schemas,
+                    timestamps,
+                    columns,
+                    timestamp_precision,
+                    is_aligned,
+                })
+            }
+            RECORDS_BATCH => {
+                let mut prefix_paths = Vec::new();
+                let mut measurements = Vec::new();
+                let mut values = Vec::new();
+                let mut timestamps = Vec::new();
+                for _ in 0..reader.u32()? {
+                    prefix_paths.push(reader.string()?);
+                    timestamps.push(reader.i64()?);
+                    let mut row_measurements = Vec::new();
+                    let mut row = Vec::new();
+                    for _ in 0..reader.u32()? {
+                        row_measurements.push(reader.string()?);
+                        row.push(reader.value()?);
+                    }
+                    measurements.push(row_measurements);
+                    values.push(row);
+                }
+                Batch::Records {
+                    prefix_paths,
+                    measurements,
+                    values,
+                    timestamps,
+                }
+            }
+            kind => return Err(format!("unknown batch kind {}", kind).into()),
+        };
+        if !reader.0.is_empty() {
+            return Err("trailing bytes after the batch".into());
+        }
+        Ok(batch)
+    }
+}
+
+fn put_len(buffer: &mut Vec<u8>, len: usize) {
+    buffer.extend_from_slice(&(len as u32).to_be_bytes());
+}
+
+fn put_str(buffer: &mut Vec<u8>, s: &str) {
+    put_len(buffer, s.len());
+    buffer.extend_from_slice(s.as_bytes());
+}
+
+fn put_value(buffer: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => buffer.push(NULL_VALUE),
+        value => buffer.append(&mut value.into()),
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err("unexpected end of the batch".into());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let value = match self.u8()? {
+            NULL_VALUE => Value::Null,
+            0 => Value::Bool(self.u8()? != 0),
+            1 => Value::Int32(i32::from_be_bytes(self.take(4)?.try_into()?)),
+            2 => Value::Int64(self.i64()?),
+            3 => Value::Float(f32::from_be_bytes(self.take(4)?.try_into()?)),
+            4 => Value::Double(f64::from_be_bytes(self.take(8)?.try_into()?)),
+            5 => Value::Text(self.string()?),
+            data_type => return Err(format!("unknown data type {}", data_type).into()),
+        };
+        Ok(value)
+    }
+}
+
+/// Frames an encoded batch as a segment entry.
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(ENTRY_HEADER_SIZE + payload.len());
+    entry.extend_from_slice(&MAGIC);
+    entry.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    entry.extend_from_slice(&crc32fast::hash(payload).to_be_bytes());
+    entry.extend_from_slice(payload);
+    entry
+}
+
+/// Parses the entries of a segment with their end offsets, corrupted bytes
+/// are skipped up to the next entry which checks out.
+fn read_entries(data: &[u8], path: &Path) -> Vec<(usize, Batch)> {
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    let mut pos = 0;
+    while pos + ENTRY_HEADER_SIZE <= data.len() {
+        match read_entry(&data[pos..]) {
+            Some((len, batch)) => {
+                pos += len;
+                entries.push((pos, batch));
+            }
+            None => {
+                skipped += 1;
+                pos += 1;
+                pos += data[pos..]
+                    .windows(MAGIC.len())
+                    .position(|window| window == MAGIC)
+                    .unwrap_or(data.len() - pos);
+            }
+        }
+    }
+    if skipped > 0 || pos < data.len() {
+        eprintln!(
+            "skipped corrupted entries of the spool segment {}",
+            path.display()
+        );
+    }
+    entries
+}
+
+fn read_entry(data: &[u8]) -> Option<(usize, Batch)> {
+    if data[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+    let len = u32::from_be_bytes(data[4..8].try_into().ok()?) as usize;
+    let crc = u32::from_be_bytes(data[8..12].try_into().ok()?);
+    let payload = data.get(ENTRY_HEADER_SIZE..ENTRY_HEADER_SIZE + len)?;
+    if crc32fast::hash(payload) != crc {
+        return None;
+    }
+    let batch = Batch::decode(payload).ok()?;
+    Some((ENTRY_HEADER_SIZE + len, batch))
+}
+
+#[derive(Debug)]
+struct Segment {
+    seq: u64,
+    path: PathBuf,
+    len: u64,
+}
+
+/// Spools batches to disk while the server is unreachable.
+///
+/// `write` sends a batch through the session unless there are spooled
+/// batches which can't be replayed yet, batches which fail with a connection
+/// error are spooled instead. Batches the server rejects while replaying are
+/// dropped with a message on stderr so they can't block the spool.
+#[derive(Debug)]
+pub struct Spool {
+    config: SpoolConfig,
+    segments: VecDeque<Segment>,
+    /// The last segment, opened for appending.
+    file: Option<File>,
+    /// Bytes of the first segment which were replayed.
+    replayed: usize,
+}
+
+impl Spool {
+    /// Opens the spool directory, the segments left by a previous process are
+    /// replayed first.
+    pub fn open(config: SpoolConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&config.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            let seq = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+            if let Some(seq) = seq {
+                let len = fs::metadata(&path)?.len();
+                segments.push(Segment { seq, path, len });
+            }
+        }
+        segments.sort_by_key(|segment| segment.seq);
+        Ok(Self {
+            config,
+            segments: segments.into(),
+            file: None,
+            replayed: 0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Size of the segment files in bytes.
+    pub fn size(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.len).sum()
+    }
+
+    /// Persists a batch after the ones already spooled.
+    pub fn append(&mut self, batch: &Batch) -> Result<()> {
+        let entry = frame(&batch.encode());
+        if self.size() + entry.len() as u64 > self.config.max_bytes {
+            return Err(SpoolFullError {
+                max_bytes: self.config.max_bytes,
+            }
+            .into());
+        }
+        let full = self
+            .segments
+            .back()
+            .is_none_or(|segment| segment.len >= self.config.segment_bytes);
+        if self.file.is_none() || full {
+            let seq = self.segments.back().map_or(0, |segment| segment.seq + 1);
+            let path = self
+                .config
+                .dir
+                .join(format!("{:020}.{}", seq, SEGMENT_EXTENSION));
+            let file = OpenOptions::new()
+                .create_new(true)
+                .append(true)
+                .open(&path)?;
+            self.file = Some(file);
+            self.segments.push_back(Segment { seq, path, len: 0 });
+        }
+
+        let file = self.file.as_mut().unwrap();
+        let res = file.write_all(&entry).and_then(|_| match self.config.sync {
+            true => file.sync_data(),
+            false => Ok(()),
+        });
+        if let Err(err) = res {
+            //Starts a new segment rather than appending after a torn entry.
+            self.file = None;
+            return Err(err.into());
+        }
+        if let Some(segment) = self.segments.back_mut() {
+            segment.len += entry.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Reads the spooled batches in order, e.g. to inspect them.
+    pub fn batches(&self) -> Result<Vec<Batch>> {
+        let mut batches = Vec::new();
+        for (index, segment) in self.segments.iter().enumerate() {
+            let data = fs::read(&segment.path)?;
+            batches.extend(
+                read_entries(&data, &segment.path)
+                    .into_iter()
+                    .filter(|(end, _)| index > 0 || *end > self.replayed)
+                    .map(|(_, batch)| batch),
+            );
+        }
+        Ok(batches)
+    }
+
+    /// Writes the spooled batches in order and deletes the segments once
+    /// they're written, returns the numbers of written batches and of the
+    /// ones dropped because the server rejected them.
+    pub fn replay<S>(&mut self, session: &mut S) -> Result<(usize, usize)>
+    where
+        S: for<'a> Session<'a>,
+    {
+        let (mut written, mut dropped) = (0, 0);
+        while let Some(segment) = self.segments.front() {
+            if self.segments.len() == 1 {
+                self.file = None;
+            }
+            let data = fs::read(&segment.path)?;
+            for (end, batch) in read_entries(&data, &segment.path) {
+                if end <= self.replayed {
+                    continue;
+                }
+                match batch.send(session) {
+                    Ok(()) => written += 1,
+                    Err(err) if is_connection_error(err.as_ref()) => return Err(err),
+                    Err(err) => {
+                        dropped += 1;
+                        eprintln!(
+                            "dropped a spooled batch rejected by the server, reason {}",
+                            err
+                        );
+                    }
+                }
+                self.replayed = end;
+            }
+            fs::remove_file(&segment.path)?;
+            self.segments.pop_front();
+            self.replayed = 0;
+        }
+        Ok((written, dropped))
+    }
+
+    /// Sends the batch through the session after the spooled ones, it's
+    /// spooled if the server can't be reached.
+    pub fn write<S>(&mut self, session: &mut S, batch: &Batch) -> Result<()>
+    where
+        S: for<'a> Session<'a>,
+    {
+        if !self.is_empty() {
+            if let Err(err) = self.replay(session) {
+                if !is_connection_error(err.as_ref()) {
+                    return Err(err);
+                }
+                return self.append(batch);
+            }
+        }
+        match batch.send(session) {
+            Err(err) if is_connection_error(err.as_ref()) => self.append(batch),
+            res => res,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+use super::Result;
+
+/// The unit of the `i64` timestamps stored by the server.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum TimestampPrecision {
+    Millisecond,
+    Microsecond,
+    Nanosecond,
+}
+
+impl TimestampPrecision {
+    /// Number of timestamp units in one second.
+    pub fn units_per_second(&self) -> i64 {
+        match self {
+            TimestampPrecision::Millisecond => 1_000,
+            TimestampPrecision::Microsecond => 1_000_000,
+            TimestampPrecision::Nanosecond => 1_000_000_000,
+        }
+    }
+}
+
+impl FromStr for TimestampPrecision {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ms" => Ok(TimestampPrecision::Millisecond),
+            "us" => Ok(TimestampPrecision::Microsecond),
+            "ns" => Ok(TimestampPrecision::Nanosecond),
+            _ => Err(format!("Illegal timestamp precision '{}'", s).into()),
+        }
+    }
+}
+
+/// A timestamp tagged with its precision, it is scaled to the server's
+/// precision when written or used in a time-range query.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Timestamp {
+    value: i64,
+    precision: TimestampPrecision,
+}
+
+impl Timestamp {
+    pub fn new(value: i64, precision: TimestampPrecision) -> Self {
+        Self { value, precision }
+    }
+
+    pub fn from_millis(millis: i64) -> Self {
+        Self::new(millis, TimestampPrecision::Millisecond)
+    }
+
+    pub fn from_micros(micros: i64) -> Self {
+        Self::new(micros, TimestampPrecision::Microsecond)
+    }
+
+    pub fn from_nanos(nanos: i64) -> Self {
+        Self::new(nanos, TimestampPrecision::Nanosecond)
+    }
+
+    pub fn now() -> Self {
+        Self::from_nanos(SystemTime::now().into_timestamp(TimestampPrecision::Nanosecond))
+    }
+
+    pub fn value(&self) -> i64 {
+        self.value
+    }
+
+    pub fn precision(&self) -> TimestampPrecision {
+        self.precision
+    }
+
+    /// Converts the timestamp to another precision, truncating towards the
+    /// past when the target precision is coarser and saturating at the `i64`
+    /// range when it's finer.
+    pub fn to_precision(&self, precision: TimestampPrecision) -> Self {
+        let from = self.precision.units_per_second();
+        let to = precision.units_per_second();
+        let value = if from <= to {
+            self.value.saturating_mul(to / from)
+        } else {
+            self.value.div_euclid(from / to)
+        };
+        Self::new(value, precision)
+    }
+}
+
+/// A point in time which can be converted to the server's `i64` timestamp.
+///
+/// Plain `i64` values are passed through untouched, they are expected to be
+/// in the server's precision already. Other times are floored towards the
+/// past and saturate at the `i64` range.
+pub trait IntoTimestamp {
+    fn into_timestamp(self, precision: TimestampPrecision) -> i64;
+}
+
+impl IntoTimestamp for i64 {
+    fn into_timestamp(self, _precision: TimestampPrecision) -> i64 {
+        self
+    }
+}
+
+impl IntoTimestamp for Timestamp {
+    fn into_timestamp(self, precision: TimestampPrecision) -> i64 {
+        self.to_precision(precision).value
+    }
+}
+
+/// Converts nanoseconds since the epoch, flooring towards the past and
+/// saturating at the `i64` range.
+fn from_epoch_nanos(nanos: i128, precision: TimestampPrecision) -> i64 {
+    let units = nanos.div_euclid((1_000_000_000 / precision.units_per_second()) as i128);
+    units.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+impl IntoTimestamp for SystemTime {
+    fn into_timestamp(self, precision: TimestampPrecision) -> i64 {
+        let nanos = match self.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_nanos() as i128,
+            Err(err) => -(err.duration().as_nanos() as i128),
+        };
+        from_epoch_nanos(nanos, precision)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: TimeZone> IntoTimestamp for DateTime<Tz> {
+    fn into_timestamp(self, precision: TimestampPrecision) -> i64 {
+        let nanos =
+            self.timestamp() as i128 * 1_000_000_000 + self.timestamp_subsec_nanos() as i128;
+        from_epoch_nanos(nanos, precision)
+    }
+}
+
+/// Converts a server timestamp into a `DateTime` in the given time zone.
+#[cfg(feature = "chrono")]
+pub fn to_date_time<Tz: TimeZone>(
+    timestamp: i64,
+    precision: TimestampPrecision,
+    tz: &Tz,
+) -> Option<DateTime<Tz>> {
+    let units_per_second = precision.units_per_second();
+    let secs = timestamp.div_euclid(units_per_second);
+    let nanos = timestamp.rem_euclid(units_per_second) * (1_000_000_000 / units_per_second);
+    tz.timestamp_opt(secs, nanos as u32).single()
+}
+
+/// Returns the `(start, end)` range covering the last `duration` until now.
+#[cfg(feature = "chrono")]
+pub fn last(duration: Duration) -> (DateTime<Utc>, DateTime<Utc>) {
+    let end = Utc::now();
+    (end - duration, end)
+}
+
+/// Parses a time zone as reported by `Session::get_time_zone`.
+///
+/// Both region names like `Asia/Shanghai` and whole-hour offsets like
+/// `+08:00` are supported.
+#[cfg(feature = "chrono")]
+pub fn parse_time_zone(time_zone: &str) -> Result<chrono_tz::Tz> {
+    if let Ok(tz) = time_zone.parse::<chrono_tz::Tz>() {
+        return Ok(tz);
+    }
+    let offset = chrono::FixedOffset::from_str(time_zone)
+        .map_err(|_| format!("Illegal time zone '{}'", time_zone))?;
+    let seconds = offset.local_minus_utc();
+    if seconds % 3600 != 0 {
+        return Err(format!("Unsupported time zone offset '{}'", time_zone).into());
+    }
+    // The Etc/GMT zones use inverted signs, Etc/GMT-8 is UTC+08:00.
+    let name = match seconds / 3600 {
+        0 => String::from("Etc/GMT"),
+        hours if hours > 0 => format!("Etc/GMT-{}", hours),
+        hours => format!("Etc/GMT+{}", -hours),
+    };
+    name.parse::<chrono_tz::Tz>()
+        .map_err(|_| format!("Unsupported time zone offset '{}'", time_zone).into())
+}
@@ -0,0 +1,179 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+use std::path::PathBuf;
+
+use typed_builder::TypedBuilder;
+
+use super::transport::Channel;
+use super::Result;
+
+/// TLS settings of a connection, it requires the `tls` feature.
+#[derive(Debug, Clone, Default, TypedBuilder)]
+#[builder(field_defaults(default, setter(into)))]
+pub struct TlsConfig {
+    /// PEM bundle of the trusted CA certificates, the Mozilla root
+    /// certificates are trusted if it's not set.
+    pub ca_file: Option<PathBuf>,
+    /// PEM certificate chain presented to the server for mutual TLS.
+    pub client_cert_file: Option<PathBuf>,
+    /// PEM private key of `client_cert_file`.
+    pub client_key_file: Option<PathBuf>,
+    /// Overrides the host name used for SNI and certificate verification.
+    pub server_name: Option<String>,
+    /// Skips the server certificate verification, only for development.
+    pub insecure: bool,
+}
+
+#[cfg(not(feature = "tls"))]
+pub(crate) fn connect(
+    _tls: &TlsConfig,
+    _host: &str,
+    _channel: Box<dyn Channel>,
+) -> Result<Box<dyn Channel>> {
+    Err("TLS connections require the 'tls' feature.".into())
+}
+
+#[cfg(feature = "tls")]
+pub(crate) use self::rustls_impl::connect;
+
+#[cfg(all(feature = "tls", test))]
+pub(crate) use self::rustls_impl::client_config;
+
+#[cfg(feature = "tls")]
+mod rustls_impl {
+    use std::sync::Arc;
+
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+    use rustls::{
+        ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+        StreamOwned,
+    };
+
+    use super::{Channel, Result, TlsConfig};
+
+    #[derive(Debug)]
+    struct InsecureVerifier(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for InsecureVerifier {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> core::result::Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    pub(crate) fn client_config(tls: &TlsConfig) -> Result<ClientConfig> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = if tls.insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(InsecureVerifier(provider)))
+        } else {
+            let mut roots = RootCertStore::empty();
+            match &tls.ca_file {
+                Some(ca_file) => {
+                    for cert in CertificateDer::pem_file_iter(ca_file).map_err(|err| {
+                        format!("failed to read CA file {}, {}", ca_file.display(), err)
+                    })? {
+                        roots.add(cert?)?;
+                    }
+                }
+                None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            }
+            builder.with_root_certificates(roots)
+        };
+
+        match (&tls.client_cert_file, &tls.client_key_file) {
+            (Some(cert_file), Some(key_file)) => {
+                let certs = CertificateDer::pem_file_iter(cert_file)
+                    .map_err(|err| {
+                        format!(
+                            "failed to read client certificate {}, {}",
+                            cert_file.display(),
+                            err
+                        )
+                    })?
+                    .collect::<core::result::Result<Vec<_>, _>>()?;
+                let key = PrivateKeyDer::from_pem_file(key_file).map_err(|err| {
+                    format!("failed to read client key {}, {}", key_file.display(), err)
+                })?;
+                Ok(builder.with_client_auth_cert(certs, key)?)
+            }
+            (None, None) => Ok(builder.with_no_client_auth()),
+            _ => {
+                Err("Both client_cert_file and client_key_file are required for mutual TLS.".into())
+            }
+        }
+    }
+
+    pub(crate) fn connect(
+        tls: &TlsConfig,
+        host: &str,
+        channel: Box<dyn Channel>,
+    ) -> Result<Box<dyn Channel>> {
+        let server_name =
+            ServerName::try_from(tls.server_name.as_deref().unwrap_or(host).to_string())?;
+        let connection = ClientConnection::new(Arc::new(client_config(tls)?), server_name)?;
+        Ok(Box::new(StreamOwned::new(connection, channel)))
+    }
+}
@@ -0,0 +1,301 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use typed_builder::TypedBuilder;
+
+use super::credential::Password;
+use super::remote::percent_decode;
+use super::{EndPoint, Result};
+
+/// A byte stream to a node, the session frames the thrift messages on it.
+pub trait Channel: Read + Write {}
+
+impl<T: Read + Write + ?Sized> Channel for T {}
+
+/// Opens the channels of the sessions instead of the built-in TCP
+/// connections, e.g. over a Unix domain socket, an SSH tunnel or an
+/// in-memory pipe in tests. TLS is still applied on top if it's configured.
+pub trait Transport: Send + Sync {
+    fn connect(&self, endpoint: &EndPoint) -> Result<Box<dyn Channel>>;
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProxyProtocol {
+    Socks5,
+    HttpConnect,
+}
+
+/// A proxy the TCP connections are tunneled through.
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(field_defaults(default, setter(into)))]
+pub struct ProxyConfig {
+    #[builder(default = ProxyProtocol::Socks5)]
+    pub protocol: ProxyProtocol,
+    /// `host:port` of the proxy.
+    #[builder(!default)]
+    pub address: String,
+    pub username: Option<String>,
+    pub password: Option<Password>,
+}
+
+/// Parses `socks5://[user:pass@]host:port` or `http://[user:pass@]host:port`.
+impl FromStr for ProxyConfig {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(url: &str) -> Result<Self> {
+        let (protocol, rest) = if let Some(rest) = url.strip_prefix("socks5://") {
+            (ProxyProtocol::Socks5, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (ProxyProtocol::HttpConnect, rest)
+        } else {
+            return Err(format!("Unsupported proxy '{}'", url).into());
+        };
+        let rest = rest.strip_suffix('/').unwrap_or(rest);
+        let (user_info, address) = match rest.rsplit_once('@') {
+            Some((user_info, address)) => (Some(user_info), address),
+            None => (None, rest),
+        };
+        if address.is_empty() {
+            return Err(format!("Proxy '{}' has no address", url).into());
+        }
+        let (username, password) = match user_info.map(|u| u.split_once(':')) {
+            Some(Some((username, password))) => (
+                Some(percent_decode(username)?),
+                Some(Password::from(percent_decode(password)?)),
+            ),
+            Some(None) => (Some(percent_decode(user_info.unwrap())?), None),
+            None => (None, None),
+        };
+        Ok(Self {
+            protocol,
+            address: address.to_string(),
+            username,
+            password,
+        })
+    }
+}
+
+/// Asks the proxy connected to by `stream` to open a tunnel to the endpoint.
+pub(crate) fn handshake<S: Read + Write>(
+    proxy: &ProxyConfig,
+    stream: &mut S,
+    endpoint: &EndPoint,
+) -> Result<()> {
+    let port = u16::try_from(endpoint.port)
+        .map_err(|_| format!("Illegal port {} of {}", endpoint.port, endpoint.ip))?;
+    match proxy.protocol {
+        ProxyProtocol::Socks5 => socks5_handshake(proxy, stream, &endpoint.ip, port),
+        ProxyProtocol::HttpConnect => http_connect(proxy, stream, &endpoint.ip, port),
+    }
+}
+
+fn socks5_handshake<S: Read + Write>(
+    proxy: &ProxyConfig,
+    stream: &mut S,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    //RFC 1928, no authentication or RFC 1929 username/password.
+    let with_password = proxy.username.is_some();
+    if with_password {
+        stream.write_all(&[5, 2, 0, 2])?;
+    } else {
+        stream.write_all(&[5, 1, 0])?;
+    }
+    let mut reply = [0_u8; 2];
+    stream.read_exact(&mut reply)?;
+    match reply {
+        [5, 0] => {}
+        [5, 2] if with_password => {
+            let username = proxy.username.as_deref().unwrap_or_default().as_bytes();
+            let password = proxy.password.as_ref().map(Password::expose);
+            let password = password.unwrap_or_default().as_bytes();
+            if username.len() > 255 || password.len() > 255 {
+                return Err("SOCKS5 username and password must not exceed 255 bytes".into());
+            }
+            let mut request = vec![1, username.len() as u8];
+            request.extend_from_slice(username);
+            request.push(password.len() as u8);
+            request.extend_from_slice(password);
+            stream.write_all(&request)?;
+            stream.read_exact(&mut reply)?;
+            if reply[1] != 0 {
+                return Err("SOCKS5 proxy rejected the username and password".into());
+            }
+        }
+        _ => return Err("SOCKS5 proxy doesn't accept any supported authentication".into()),
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(format!("Host name '{}' is too long for SOCKS5", host).into());
+            }
+            request.push(3);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0_u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        let reason = match reply[1] {
+            1 => "general failure",
+            2 => "connection not allowed by ruleset",
+            3 => "network unreachable",
+            4 => "host unreachable",
+            5 => "connection refused",
+            6 => "TTL expired",
+            7 => "command not supported",
+            8 => "address type not supported",
+            _ => "unknown error",
+        };
+        return Err(format!(
+            "SOCKS5 proxy failed to connect to {}:{}, {}",
+            host, port, reason
+        )
+        .into());
+    }
+    //Skip the bound address and port.
+    let address_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0_u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        atyp => return Err(format!("Unknown SOCKS5 address type {}", atyp).into()),
+    };
+    stream.read_exact(&mut vec![0_u8; address_len + 2])?;
+    Ok(())
+}
+
+fn http_connect<S: Read + Write>(
+    proxy: &ProxyConfig,
+    stream: &mut S,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    let authority = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+    if let Some(username) = &proxy.username {
+        let password = proxy.password.as_ref().map(Password::expose);
+        let credentials = format!("{}:{}", username, password.unwrap_or_default());
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64(credentials.as_bytes())
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    //Read byte by byte so nothing after the header is consumed.
+    let mut response = Vec::new();
+    let mut byte = [0_u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 8192 {
+            return Err("HTTP proxy response header is too large".into());
+        }
+        stream.read_exact(&mut byte)?;
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(format!(
+            "HTTP proxy failed to connect to {}, {}",
+            authority, status_line
+        )
+        .into()),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// One half of a shared channel, the framed transports only read after a
+/// request is flushed so the halves never wait on each other.
+struct ChannelHalf(Rc<RefCell<Box<dyn Channel>>>);
+
+impl Read for ChannelHalf {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl Write for ChannelHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Splits a channel into the halves read and written by the thrift transports.
+pub(crate) fn split(channel: Box<dyn Channel>) -> (Box<dyn Read>, Box<dyn Write>) {
+    let channel = Rc::new(RefCell::new(channel));
+    (
+        Box::new(ChannelHalf(channel.clone())),
+        Box::new(ChannelHalf(channel)),
+    )
+}
@@ -0,0 +1,596 @@
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//
+
+use std::collections::{BTreeMap, VecDeque};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use typed_builder::TypedBuilder;
+
+use super::error::QueueFullError;
+use super::remote::{Config, RpcSession};
+use super::schema::AutoSchemaConfig;
+use super::time::{IntoTimestamp, TimestampPrecision};
+use super::{Result, Session, Tablet, Value};
+
+/// When a `BufferedWriter` flushes, whichever limit is reached first.
+#[derive(Debug, Clone, TypedBuilder)]
+#[builder(field_defaults(default, setter(into)))]
+pub struct WriterConfig {
+    #[builder(default = 1000)]
+    pub max_rows: usize,
+    /// Estimated size of the buffered values.
+    #[builder(default = 1024 * 1024)]
+    pub max_bytes: usize,
+    /// Age of the oldest buffered point, it's checked on `write` and `flush_if_due`.
+    #[builder(default = Duration::from_secs(1))]
+    pub max_latency: Duration,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// The points of one device waiting to be written.
+#[derive(Debug, Default)]
+struct DeviceBuffer {
+    timestamps: Vec<i64>,
+    measurements: Vec<Vec<String>>,
+    values: Vec<Vec<Value>>,
+}
+
+impl DeviceBuffer {
+    /// Builds a tablet if every row has the same measurements and types and
+    /// no nulls, the buffer is given back otherwise. The buffered timestamps
+    /// are in `precision` already, the schemas follow the `auto_schema` policy.
+    fn into_tablet(
+        self,
+        device_id: &str,
+        precision: TimestampPrecision,
+        auto_schema: &AutoSchemaConfig,
+    ) -> core::result::Result<Tablet, Self> {
+        let (measurements, first_row) = match (self.measurements.first(), self.values.first()) {
+            (Some(measurements), Some(values)) => (measurements, values),
+            _ => return Err(self),
+        };
+        let schemas: Option<Vec<_>> = measurements
+            .iter()
+            .zip(first_row)
+            .map(|(measurement, value)| auto_schema.schema_of(measurement, value))
+            .collect();
+        let schemas = match schemas {
+            Some(schemas) => schemas,
+            None => return Err(self),
+        };
+        let uniform =
+            self.measurements
+                .iter()
+                .zip(self.values.iter())
+                .all(|(row_measurements, row)| {
+                    row_measurements == measurements
+                        && row
+                            .iter()
+                            .map(Value::data_type)
+                            .eq(schemas.iter().map(|schema| Some(schema.data_type.clone())))
+                });
+        if !uniform {
+            return Err(self);
+        }
+
+        let mut tablet = Tablet::new(device_id, schemas);
+        tablet.set_timestamp_precision(precision);
+        for (row, timestamp) in self.values.into_iter().zip(self.timestamps) {
+            tablet
+                .add_row(row, timestamp)
+                .expect("the rows were checked against the schemas");
+        }
+        tablet.sort();
+        Ok(tablet)
+    }
+}
+
+/// Buffers single points and writes them in batches, one tablet per device
+/// when the rows of a device share their measurements, otherwise with
+/// `insert_records_of_one_device`.
+///
+/// The buffer is emptied by a flush even if the write fails. Dropping the
+/// writer flushes it and prints the error, call `close` to handle it instead.
+pub struct BufferedWriter<S>
+where
+    S: for<'a> Session<'a>,
+{
+    session: Option<S>,
+    config: WriterConfig,
+    buffers: BTreeMap<String, DeviceBuffer>,
+    rows: usize,
+    bytes: usize,
+    oldest: Option<Instant>,
+}
+
+impl<S> BufferedWriter<S>
+where
+    S: for<'a> Session<'a>,
+{
+    /// Wraps an opened session.
+    pub fn new(session: S, config: WriterConfig) -> Self {
+        Self {
+            session: Some(session),
+            config,
+            buffers: BTreeMap::new(),
+            rows: 0,
+            bytes: 0,
+            oldest: None,
+        }
+    }
+
+    pub fn session(&self) -> &S {
+        self.session.as_ref().unwrap()
+    }
+
+    pub fn session_mut(&mut self) -> &mut S {
+        self.session.as_mut().unwrap()
+    }
+
+    /// The precision the points are buffered in.
+    fn timestamp_precision(&self) -> TimestampPrecision {
+        self.session()
+            .server_info()
+            .map(|info| info.timestamp_precision())
+            .unwrap_or(TimestampPrecision::Millisecond)
+    }
+
+    /// Number of buffered points.
+    pub fn buffered_rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Buffers a point, the buffer is flushed if a limit is reached.
+    pub fn write<T>(
+        &mut self,
+        device_id: &str,
+        measurements: Vec<&str>,
+        values: Vec<Value>,
+        timestamp: T,
+    ) -> Result<()>
+    where
+        T: IntoTimestamp,
+    {
+        if measurements.len() != values.len() {
+            return Err(format!(
+                "measurements '{:?}' must match values '{:?}'",
+                measurements, values
+            )
+            .into());
+        }
+        let precision = self.timestamp_precision();
+
+        self.bytes += 8 + values.iter().map(value_size).sum::<usize>();
+        self.rows += 1;
+        self.oldest.get_or_insert_with(Instant::now);
+        let buffer = self.buffers.entry(device_id.to_string()).or_default();
+        buffer.timestamps.push(timestamp.into_timestamp(precision));
+        buffer
+            .measurements
+            .push(measurements.iter().map(ToString::to_string).collect());
+        buffer.values.push(values);
+
+        if self.rows >= self.config.max_rows || self.bytes >= self.config.max_bytes {
+            self.flush()
+        } else {
+            self.flush_if_due()
+        }
+    }
+
+    /// Flushes the buffer if the oldest point is older than `max_latency`.
+    pub fn flush_if_due(&mut self) -> Result<()> {
+        match self.oldest {
+            Some(oldest) if oldest.elapsed() >= self.config.max_latency => self.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes all buffered points, the first error is returned after every
+    /// device was tried.
+    pub fn flush(&mut self) -> Result<()> {
+        let precision = self.timestamp_precision();
+        let buffers = mem::take(&mut self.buffers);
+        self.rows = 0;
+        self.bytes = 0;
+        self.oldest = None;
+
+        let session = self.session.as_mut().unwrap();
+        //The schemas only matter to the timeseries auto-schema creates.
+        let auto_schema = session.auto_schema().cloned().unwrap_or_default();
+        let mut first_err = None;
+        let mut tablets = Vec::new();
+        for (device_id, buffer) in buffers {
+            let buffer = match buffer.into_tablet(&device_id, precision, &auto_schema) {
+                Ok(tablet) => {
+                    tablets.push(tablet);
+                    continue;
+                }
+                Err(buffer) => buffer,
+            };
+            let res = session.insert_records_of_one_device(
+                &device_id,
+                buffer.timestamps,
+                buffer
+                    .measurements
+                    .iter()
+                    .map(|row| row.iter().map(String::as_str).collect())
+                    .collect(),
+                buffer.values,
+                false,
+            );
+            if let Err(err) = res {
+                first_err.get_or_insert(err);
+            }
+        }
+        if !tablets.is_empty() {
+            if let Err(err) = session.insert_tablets(tablets.iter().collect()) {
+                first_err.get_or_insert(err);
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes the buffer and returns the session.
+    pub fn close(mut self) -> Result<S> {
+        self.flush()?;
+        Ok(self.session.take().unwrap())
+    }
+}
+
+impl<S> Drop for BufferedWriter<S>
+where
+    S: for<'a> Session<'a>,
+{
+    fn drop(&mut self) {
+        if self.session.is_some() && self.rows > 0 {
+            self.flush().unwrap_or_else(|err| {
+                eprintln!("error flushing the buffered writer on drop, reason {}", err)
+            });
+        }
+    }
+}
+
+fn value_size(value: &Value) -> usize {
+    match value {
+        Value::Bool(_) => 1,
+        Value::Int32(_) | Value::Float(_) => 4,
+        Value::Int64(_) | Value::Double(_) => 8,
+        Value::Text(t) => 4 + t.len(),
+        Value::Null => 0,
+    }
+}
+
+/// What `WriterHandle::write` does when the queue is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Waits until a worker takes points from the queue.
+    Block,
+    /// Discards the oldest queued point, it's counted by `dropped_points`.
+    DropOldest,
+    /// Returns a `QueueFullError`.
+    Error,
+}
+
+#[derive(Debug, Clone, TypedBuilder)]
+#[builder(field_defaults(default, setter(into)))]
+pub struct WriterHandleConfig {
+    /// Maximum number of queued points.
+    #[builder(default = 10000)]
+    pub queue_capacity: usize,
+    /// Number of background threads, each owns a session.
+    #[builder(default = 1)]
+    pub workers: usize,
+    #[builder(default = OverflowPolicy::Block)]
+    pub overflow_policy: OverflowPolicy,
+    /// Batching of each worker.
+    pub writer: WriterConfig,
+}
+
+impl Default for WriterHandleConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+#[derive(Debug)]
+struct Point {
+    device_id: String,
+    measurements: Vec<String>,
+    values: Vec<Value>,
+    timestamp: i64,
+}
+
+#[derive(Default)]
+struct QueueState {
+    points: VecDeque<Point>,
+    closed: bool,
+}
+
+/// The state shared by the handles and the workers.
+struct Shared {
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped_points: AtomicU64,
+    failed_flushes: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Shared {
+    fn push(&self, point: Point) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        while !state.closed && state.points.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::Block => state = self.not_full.wait(state).unwrap(),
+                OverflowPolicy::DropOldest => {
+                    state.points.pop_front();
+                    self.dropped_points.fetch_add(1, Ordering::Relaxed);
+                }
+                OverflowPolicy::Error => {
+                    return Err(QueueFullError {
+                        capacity: self.capacity,
+                    }
+                    .into())
+                }
+            }
+        }
+        if state.closed {
+            return Err("writer is closed".into());
+        }
+        state.points.push_back(point);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for points and takes at most `max` of them, the
+    /// flag tells if the queue is closed and drained.
+    fn take(&self, max: usize, timeout: Duration) -> (Vec<Point>, bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.points.is_empty() && !state.closed {
+            state = self.not_empty.wait_timeout(state, timeout).unwrap().0;
+        }
+        let count = state.points.len().min(max.max(1));
+        let points: Vec<Point> = state.points.drain(..count).collect();
+        if !points.is_empty() {
+            self.not_full.notify_all();
+        }
+        (points, state.closed && state.points.is_empty())
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    fn record_failure(&self, err: &dyn std::error::Error) {
+        self.failed_flushes.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(err.to_string());
+    }
+}
+
+/// Closes the queue and waits for the workers once the last handle is dropped.
+struct Workers {
+    shared: Arc<Shared>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    timestamp_precision: TimestampPrecision,
+}
+
+impl Workers {
+    fn join(&self) {
+        self.shared.close();
+        for thread in self.threads.lock().unwrap().drain(..) {
+            if thread.join().is_err() {
+                eprintln!("a writer thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// A `Send + Clone` handle which queues points for background threads, each
+/// of them writes with its own session through a `BufferedWriter`.
+///
+/// Writes happen asynchronously, so their failures are only counted by
+/// `failed_flushes`. The queue is drained and flushed by `close` or when the
+/// last handle is dropped.
+#[derive(Clone)]
+pub struct WriterHandle {
+    workers: Arc<Workers>,
+}
+
+impl WriterHandle {
+    /// Opens a session per worker and starts the workers, it fails if any
+    /// session can't be opened.
+    pub fn spawn(config: Config, handle_config: WriterHandleConfig) -> Result<Self> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(QueueState::default()),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: handle_config.queue_capacity.max(1),
+            policy: handle_config.overflow_policy,
+            dropped_points: AtomicU64::new(0),
+            failed_flushes: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        });
+
+        let (opened_tx, opened_rx) = mpsc::channel();
+        let mut threads = Vec::new();
+        for index in 0..handle_config.workers.max(1) {
+            let config = config.clone();
+            let writer_config = handle_config.writer.clone();
+            let shared = shared.clone();
+            let opened_tx = opened_tx.clone();
+            let thread = thread::Builder::new()
+                .name(format!("iotdb-writer-{}", index))
+                .spawn(move || {
+                    let session = RpcSession::new(config).and_then(|mut session| {
+                        session.open()?;
+                        Ok(session)
+                    });
+                    match session {
+                        Ok(session) => {
+                            let precision = session
+                                .server_info()
+                                .map(|info| info.timestamp_precision())
+                                .unwrap_or(TimestampPrecision::Millisecond);
+                            let _ = opened_tx.send(Ok(precision));
+                            drop(opened_tx);
+                            work(BufferedWriter::new(session, writer_config), &shared);
+                        }
+                        Err(err) => {
+                            let _ = opened_tx.send(Err(err.to_string()));
+                        }
+                    }
+                })?;
+            threads.push(thread);
+        }
+        drop(opened_tx);
+
+        let mut precision = Ok(TimestampPrecision::Millisecond);
+        for opened in opened_rx {
+            if precision.is_ok() {
+                precision = opened;
+            }
+        }
+        let workers = Workers {
+            shared,
+            threads: Mutex::new(threads),
+            timestamp_precision: *precision
+                .as_ref()
+                .unwrap_or(&TimestampPrecision::Millisecond),
+        };
+        match precision {
+            Ok(_) => Ok(Self {
+                workers: Arc::new(workers),
+            }),
+            Err(err) => {
+                //Stops the workers which opened their sessions.
+                drop(workers);
+                Err(format!("failed to open the writer sessions, {}", err).into())
+            }
+        }
+    }
+
+    /// Queues a point, what happens if the queue is full depends on the
+    /// `OverflowPolicy`.
+    pub fn write<T>(
+        &self,
+        device_id: &str,
+        measurements: Vec<&str>,
+        values: Vec<Value>,
+        timestamp: T,
+    ) -> Result<()>
+    where
+        T: IntoTimestamp,
+    {
+        if measurements.len() != values.len() {
+            return Err(format!(
+                "measurements '{:?}' must match values '{:?}'",
+                measurements, values
+            )
+            .into());
+        }
+        self.workers.shared.push(Point {
+            device_id: device_id.to_string(),
+            measurements: measurements.iter().map(ToString::to_string).collect(),
+            values,
+            timestamp: timestamp.into_timestamp(self.workers.timestamp_precision),
+        })
+    }
+
+    /// Number of points waiting in the queue.
+    pub fn queue_depth(&self) -> usize {
+        self.workers.shared.state.lock().unwrap().points.len()
+    }
+
+    /// Number of points discarded by `OverflowPolicy::DropOldest`.
+    pub fn dropped_points(&self) -> u64 {
+        self.workers.shared.dropped_points.load(Ordering::Relaxed)
+    }
+
+    /// Number of flushes of the workers which failed, a flush counts once
+    /// however many of its points failed.
+    pub fn failed_flushes(&self) -> u64 {
+        self.workers.shared.failed_flushes.load(Ordering::Relaxed)
+    }
+
+    /// Message of the latest failed flush.
+    pub fn last_error(&self) -> Option<String> {
+        self.workers.shared.last_error.lock().unwrap().clone()
+    }
+
+    /// Stops accepting points and waits until the workers wrote the queued
+    /// ones, the other handles fail to write afterwards.
+    pub fn close(&self) {
+        self.workers.join();
+    }
+}
+
+fn work(mut writer: BufferedWriter<RpcSession>, shared: &Shared) {
+    let max_latency = writer.config.max_latency;
+    let max_rows = writer.config.max_rows;
+    loop {
+        let (points, done) = shared.take(max_rows, max_latency);
+        for point in points {
+            let res = writer.write(
+                &point.device_id,
+                point.measurements.iter().map(String::as_str).collect(),
+                point.values,
+                point.timestamp,
+            );
+            if let Err(err) = res {
+                shared.record_failure(err.as_ref());
+            }
+        }
+        if done {
+            break;
+        }
+        if let Err(err) = writer.flush_if_due() {
+            shared.record_failure(err.as_ref());
+        }
+    }
+    match writer.close() {
+        Ok(mut session) => {
+            if let Err(err) = session.close() {
+                eprintln!("error closing the writer session, reason {}", err);
+            }
+        }
+        Err(err) => shared.record_failure(err.as_ref()),
+    }
+}
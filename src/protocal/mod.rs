@@ -17,6 +17,9 @@
 // under the License.
 //
 
+use std::fmt;
+use std::str::FromStr;
+
 pub const FLAG: u8 = 0x80;
 
 pub const SUCCESS_STATUS: i32 = 200;
@@ -107,6 +110,52 @@ impl From<TSDataType> for i32 {
     }
 }
 
+impl TryFrom<i32> for TSDataType {
+    type Error = String;
+
+    fn try_from(t: i32) -> Result<Self, Self::Error> {
+        match t {
+            0 => Ok(TSDataType::Boolean),
+            1 => Ok(TSDataType::Int32),
+            2 => Ok(TSDataType::Int64),
+            3 => Ok(TSDataType::Float),
+            4 => Ok(TSDataType::Double),
+            5 => Ok(TSDataType::Text),
+            _ => Err(format!("Illegal datatype {}", t)),
+        }
+    }
+}
+
+impl FromStr for TSDataType {
+    type Err = String;
+
+    /// Parses the name used in IoTDB statements, case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "BOOLEAN" => Ok(TSDataType::Boolean),
+            "INT32" => Ok(TSDataType::Int32),
+            "INT64" => Ok(TSDataType::Int64),
+            "FLOAT" => Ok(TSDataType::Float),
+            "DOUBLE" => Ok(TSDataType::Double),
+            "TEXT" => Ok(TSDataType::Text),
+            _ => Err(format!("Illegal datatype '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for TSDataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TSDataType::Boolean => "BOOLEAN",
+            TSDataType::Int32 => "INT32",
+            TSDataType::Int64 => "INT64",
+            TSDataType::Float => "FLOAT",
+            TSDataType::Double => "DOUBLE",
+            TSDataType::Text => "TEXT",
+        })
+    }
+}
+
 impl From<&String> for TSDataType {
     fn from(t: &String) -> Self {
         match t.as_str() {
@@ -150,6 +199,61 @@ impl From<TSEncoding> for i32 {
     }
 }
 
+impl TryFrom<i32> for TSEncoding {
+    type Error = String;
+
+    fn try_from(t: i32) -> Result<Self, Self::Error> {
+        match t {
+            0 => Ok(TSEncoding::Plain),
+            1 => Ok(TSEncoding::PlainDictionary),
+            2 => Ok(TSEncoding::RLE),
+            3 => Ok(TSEncoding::Diff),
+            4 => Ok(TSEncoding::Ts2diff),
+            5 => Ok(TSEncoding::Bitmap),
+            6 => Ok(TSEncoding::GorillaV1),
+            7 => Ok(TSEncoding::Regular),
+            8 => Ok(TSEncoding::Gorilla),
+            _ => Err(format!("Illegal encoding {}", t)),
+        }
+    }
+}
+
+impl FromStr for TSEncoding {
+    type Err = String;
+
+    /// Parses the name used in IoTDB statements, case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "PLAIN" => Ok(TSEncoding::Plain),
+            "PLAIN_DICTIONARY" => Ok(TSEncoding::PlainDictionary),
+            "RLE" => Ok(TSEncoding::RLE),
+            "DIFF" => Ok(TSEncoding::Diff),
+            "TS_2DIFF" => Ok(TSEncoding::Ts2diff),
+            "BITMAP" => Ok(TSEncoding::Bitmap),
+            "GORILLA_V1" => Ok(TSEncoding::GorillaV1),
+            "REGULAR" => Ok(TSEncoding::Regular),
+            "GORILLA" => Ok(TSEncoding::Gorilla),
+            _ => Err(format!("Illegal encoding '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for TSEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TSEncoding::Plain => "PLAIN",
+            TSEncoding::PlainDictionary => "PLAIN_DICTIONARY",
+            TSEncoding::RLE => "RLE",
+            TSEncoding::Diff => "DIFF",
+            TSEncoding::Ts2diff => "TS_2DIFF",
+            TSEncoding::Bitmap => "BITMAP",
+            TSEncoding::GorillaV1 => "GORILLA_V1",
+            TSEncoding::Regular => "REGULAR",
+            TSEncoding::Gorilla => "GORILLA",
+        })
+    }
+}
+
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum TSCompressionType {
     UNCOMPRESSED = 0,
@@ -176,3 +280,55 @@ impl From<TSCompressionType> for i32 {
         }
     }
 }
+
+impl TryFrom<i32> for TSCompressionType {
+    type Error = String;
+
+    fn try_from(t: i32) -> Result<Self, Self::Error> {
+        match t {
+            0 => Ok(TSCompressionType::UNCOMPRESSED),
+            1 => Ok(TSCompressionType::SNAPPY),
+            2 => Ok(TSCompressionType::GZIP),
+            3 => Ok(TSCompressionType::LZO),
+            4 => Ok(TSCompressionType::SDT),
+            5 => Ok(TSCompressionType::PAA),
+            6 => Ok(TSCompressionType::PLA),
+            7 => Ok(TSCompressionType::LZ4),
+            _ => Err(format!("Illegal compression type {}", t)),
+        }
+    }
+}
+
+impl FromStr for TSCompressionType {
+    type Err = String;
+
+    /// Parses the name used in IoTDB statements, case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "UNCOMPRESSED" => Ok(TSCompressionType::UNCOMPRESSED),
+            "SNAPPY" => Ok(TSCompressionType::SNAPPY),
+            "GZIP" => Ok(TSCompressionType::GZIP),
+            "LZO" => Ok(TSCompressionType::LZO),
+            "SDT" => Ok(TSCompressionType::SDT),
+            "PAA" => Ok(TSCompressionType::PAA),
+            "PLA" => Ok(TSCompressionType::PLA),
+            "LZ4" => Ok(TSCompressionType::LZ4),
+            _ => Err(format!("Illegal compression type '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for TSCompressionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TSCompressionType::UNCOMPRESSED => "UNCOMPRESSED",
+            TSCompressionType::SNAPPY => "SNAPPY",
+            TSCompressionType::GZIP => "GZIP",
+            TSCompressionType::LZO => "LZO",
+            TSCompressionType::SDT => "SDT",
+            TSCompressionType::PAA => "PAA",
+            TSCompressionType::PLA => "PLA",
+            TSCompressionType::LZ4 => "LZ4",
+        })
+    }
+}